@@ -0,0 +1,370 @@
+//! Unpack tar archives from untrusted sources
+//!
+//! Archives are downloaded from a mirror that may be misconfigured or compromised, so every
+//! entry is validated before it is written. Entries are rejected when:
+//!
+//! - The path is absolute i.e. `/etc/profile`
+//! - The path contains `..` i.e. `bin/../../etc/profile`
+//! - The entry would be written through a symlink that points outside of the destination
+//! - A symlink or hardlink points outside of the destination, symlinks are resolved through the
+//!   links already unpacked
+//!
+//! A number of leading path components can be stripped from every entry, like
+//! `tar --strip-components`. Entries with no path left after stripping are skipped.
+use std::fmt::Display;
+use std::io::{self, Read};
+use std::path::{Component, Path, PathBuf};
+use tar::{Archive, EntryType};
+
+#[derive(thiserror::Error, Debug)]
+pub(crate) enum UnpackError {
+    #[error("Could not read archive: {0}")]
+    CouldNotRead(io::Error),
+
+    #[error("Could not unpack `{0}` from archive: {1}")]
+    CouldNotUnpackEntry(PathBuf, io::Error),
+
+    #[error("Refusing to unpack `{entry}` from archive, {reason}")]
+    UnsafeEntry {
+        entry: PathBuf,
+        reason: UnsafeReason,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum UnsafeReason {
+    AbsolutePath,
+    ParentDir,
+    OutsideDestination,
+    LinkOutsideDestination(PathBuf),
+}
+
+impl Display for UnsafeReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            UnsafeReason::AbsolutePath => write!(f, "absolute paths are not allowed"),
+            UnsafeReason::ParentDir => write!(f, "paths containing `..` are not allowed"),
+            UnsafeReason::OutsideDestination => {
+                write!(f, "it would be written outside of the destination")
+            }
+            UnsafeReason::LinkOutsideDestination(link) => write!(
+                f,
+                "it links to `{}` outside of the destination",
+                link.display()
+            ),
+        }
+    }
+}
+
+/// Unpack the tar archive read from `reader` into `destination`
+///
+/// The destination is created if it does not exist.
+pub(crate) fn unpack(
+    reader: impl Read,
+    destination: &Path,
+    strip_components: usize,
+) -> Result<(), UnpackError> {
+    let unpack_error = |path: &Path| {
+        let path = path.to_path_buf();
+        move |error| UnpackError::CouldNotUnpackEntry(path, error)
+    };
+    fs_err::create_dir_all(destination).map_err(unpack_error(destination))?;
+    let destination = fs_err::canonicalize(destination).map_err(unpack_error(destination))?;
+
+    let mut archive = Archive::new(reader);
+    for entry in archive.entries().map_err(UnpackError::CouldNotRead)? {
+        let mut entry = entry.map_err(UnpackError::CouldNotRead)?;
+        let entry_path = entry
+            .path()
+            .map_err(UnpackError::CouldNotRead)?
+            .into_owned();
+        let unsafe_entry = |reason| UnpackError::UnsafeEntry {
+            entry: entry_path.clone(),
+            reason,
+        };
+
+        let Some(relative) = relative_path(&entry_path, strip_components).map_err(unsafe_entry)?
+        else {
+            continue;
+        };
+        let (Some(parent), Some(file_name)) = (relative.parent(), relative.file_name()) else {
+            continue;
+        };
+
+        // Parents are resolved on disk so that a symlink from an earlier entry cannot be used to
+        // write outside of the destination
+        let parent = destination.join(parent);
+        fs_err::create_dir_all(&parent).map_err(unpack_error(&entry_path))?;
+        let parent = fs_err::canonicalize(&parent).map_err(unpack_error(&entry_path))?;
+        if !parent.starts_with(&destination) {
+            return Err(unsafe_entry(UnsafeReason::OutsideDestination));
+        }
+        let target = parent.join(file_name);
+
+        match entry.header().entry_type() {
+            EntryType::Symlink => {
+                let link = entry
+                    .link_name()
+                    .map_err(UnpackError::CouldNotRead)?
+                    .unwrap_or_default()
+                    .into_owned();
+                if !resolves_within(&parent, &link, &destination) {
+                    return Err(unsafe_entry(UnsafeReason::LinkOutsideDestination(link)));
+                }
+            }
+            EntryType::Link => {
+                let link = entry
+                    .link_name()
+                    .map_err(UnpackError::CouldNotRead)?
+                    .unwrap_or_default()
+                    .into_owned();
+                let source = match relative_path(&link, strip_components) {
+                    Ok(Some(source)) => fs_err::canonicalize(destination.join(source))
+                        .map_err(unpack_error(&entry_path))?,
+                    _ => return Err(unsafe_entry(UnsafeReason::LinkOutsideDestination(link))),
+                };
+                if !source.starts_with(&destination) {
+                    return Err(unsafe_entry(UnsafeReason::LinkOutsideDestination(link)));
+                }
+                if target.exists() {
+                    fs_err::remove_file(&target).map_err(unpack_error(&entry_path))?;
+                }
+                fs_err::hard_link(source, &target).map_err(unpack_error(&entry_path))?;
+                continue;
+            }
+            _ => {}
+        }
+
+        entry.unpack(&target).map_err(unpack_error(&entry_path))?;
+    }
+    Ok(())
+}
+
+/// The path of an entry relative to the destination, `None` when nothing is left after stripping
+fn relative_path(path: &Path, strip_components: usize) -> Result<Option<PathBuf>, UnsafeReason> {
+    let mut normal = Vec::new();
+    for component in path.components() {
+        match component {
+            Component::Prefix(_) | Component::RootDir => return Err(UnsafeReason::AbsolutePath),
+            Component::ParentDir => return Err(UnsafeReason::ParentDir),
+            Component::CurDir => {}
+            Component::Normal(part) => normal.push(part),
+        }
+    }
+
+    Ok(Some(
+        normal
+            .into_iter()
+            .skip(strip_components)
+            .collect::<PathBuf>(),
+    )
+    .filter(|relative| relative.components().next().is_some()))
+}
+
+/// Whether a symlink in `dir` pointing at `link` resolves inside of `root`
+///
+/// Components that exist are resolved on disk, so a `..` after a symlink from an earlier entry
+/// is followed from where that symlink points. A `..` after a component that does not exist yet
+/// is rejected, a later entry could make that component a symlink.
+fn resolves_within(dir: &Path, link: &Path, root: &Path) -> bool {
+    let mut resolved = dir.to_path_buf();
+    let mut on_disk = true;
+    for component in link.components() {
+        match component {
+            Component::Prefix(_) | Component::RootDir => return false,
+            Component::CurDir => {}
+            Component::ParentDir => {
+                if !on_disk || !resolved.pop() {
+                    return false;
+                }
+            }
+            Component::Normal(part) => {
+                resolved.push(part);
+                if on_disk {
+                    match fs_err::canonicalize(&resolved) {
+                        Ok(real) => resolved = real,
+                        Err(_) => on_disk = false,
+                    }
+                }
+            }
+        }
+        if !resolved.starts_with(root) {
+            return false;
+        }
+    }
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    enum Entry<'a> {
+        File(&'a str, &'a str),
+        Symlink(&'a str, &'a str),
+        Hardlink(&'a str, &'a str),
+    }
+
+    /// Builds an archive without the path validation of `tar::Builder` so unsafe entries can be
+    /// written
+    fn archive(entries: &[Entry<'_>]) -> Vec<u8> {
+        let mut builder = tar::Builder::new(Vec::new());
+        for entry in entries {
+            let mut header = tar::Header::new_old();
+            let (path, link, contents, entry_type) = match entry {
+                Entry::File(path, contents) => (path, "", *contents, EntryType::Regular),
+                Entry::Symlink(path, link) => (path, *link, "", EntryType::Symlink),
+                Entry::Hardlink(path, link) => (path, *link, "", EntryType::Link),
+            };
+            let old = header.as_old_mut();
+            old.name[..path.len()].copy_from_slice(path.as_bytes());
+            old.linkname[..link.len()].copy_from_slice(link.as_bytes());
+            header.set_entry_type(entry_type);
+            header.set_size(contents.len() as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+            builder.append(&header, contents.as_bytes()).unwrap();
+        }
+        builder.into_inner().unwrap()
+    }
+
+    fn unsafe_reason(result: Result<(), UnpackError>) -> UnsafeReason {
+        match result {
+            Err(UnpackError::UnsafeEntry { reason, .. }) => reason,
+            other => panic!("Expected an unsafe entry error, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_unpack() {
+        let dir = tempfile::tempdir().unwrap();
+        let destination = dir.path().join("layer");
+        let tar = archive(&[
+            Entry::File("bin/ruby", "ruby"),
+            Entry::Symlink("bin/irb", "ruby"),
+            Entry::Symlink("lib/current", "../bin"),
+            Entry::Hardlink("bin/ruby3", "bin/ruby"),
+        ]);
+
+        unpack(tar.as_slice(), &destination, 0).unwrap();
+        assert_eq!(
+            "ruby",
+            fs_err::read_to_string(destination.join("bin/irb")).unwrap()
+        );
+        assert_eq!(
+            "ruby",
+            fs_err::read_to_string(destination.join("lib/current/ruby3")).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_strip_components() {
+        let dir = tempfile::tempdir().unwrap();
+        let tar = archive(&[
+            Entry::File("ruby-3.3.6/bin/ruby", "ruby"),
+            Entry::Hardlink("ruby-3.3.6/bin/ruby3", "ruby-3.3.6/bin/ruby"),
+            Entry::File("README", "skipped"),
+        ]);
+
+        unpack(tar.as_slice(), dir.path(), 1).unwrap();
+        assert!(dir.path().join("bin/ruby").exists());
+        assert!(dir.path().join("bin/ruby3").exists());
+        assert!(!dir.path().join("README").exists());
+        assert!(!dir.path().join("ruby-3.3.6").exists());
+    }
+
+    #[test]
+    fn test_rejects_unsafe_paths() {
+        let dir = tempfile::tempdir().unwrap();
+        let destination = dir.path().join("layer");
+
+        let error = unpack(
+            archive(&[Entry::File("/tmp/evil", "")]).as_slice(),
+            &destination,
+            0,
+        )
+        .unwrap_err();
+        assert_eq!(
+            "Refusing to unpack `/tmp/evil` from archive, absolute paths are not allowed",
+            error.to_string()
+        );
+
+        assert_eq!(
+            UnsafeReason::ParentDir,
+            unsafe_reason(unpack(
+                archive(&[Entry::File("bin/../../evil", "")]).as_slice(),
+                &destination,
+                0,
+            ))
+        );
+        assert!(!dir.path().join("evil").exists());
+    }
+
+    #[test]
+    fn test_rejects_links_outside_destination() {
+        let dir = tempfile::tempdir().unwrap();
+        let destination = dir.path().join("layer");
+
+        for entries in [
+            vec![Entry::Symlink("bin/etc", "/etc")],
+            vec![Entry::Symlink("bin/up", "../../")],
+            vec![Entry::Hardlink("bin/passwd", "/etc/passwd")],
+            vec![Entry::Hardlink("bin/passwd", "../outside")],
+        ] {
+            assert!(matches!(
+                unsafe_reason(unpack(archive(&entries).as_slice(), &destination, 0)),
+                UnsafeReason::LinkOutsideDestination(_)
+            ));
+        }
+    }
+
+    #[test]
+    fn test_rejects_writing_through_symlinks() {
+        let dir = tempfile::tempdir().unwrap();
+        let destination = dir.path().join("layer");
+        let tar = archive(&[
+            Entry::Symlink("here", "."),
+            Entry::Symlink("here/up", ".."),
+            Entry::File("here/up/evil", ""),
+        ]);
+
+        // `here/up` resolves lexically to the destination, but on disk `here` is the destination
+        // so `up` would point to its parent
+        assert!(matches!(
+            unsafe_reason(unpack(tar.as_slice(), &destination, 0)),
+            UnsafeReason::LinkOutsideDestination(_) | UnsafeReason::OutsideDestination
+        ));
+        assert!(!dir.path().join("evil").exists());
+    }
+
+    #[test]
+    fn test_rejects_chained_symlinks() {
+        let dir = tempfile::tempdir().unwrap();
+        let destination = dir.path().join("layer");
+
+        // `here/..` resolves lexically to the destination, but on disk `here` is the destination
+        // so `escape` would point to its parent
+        let tar = archive(&[
+            Entry::Symlink("here", "."),
+            Entry::Symlink("escape", "here/.."),
+            Entry::File("escape/evil", ""),
+        ]);
+        assert_eq!(
+            UnsafeReason::LinkOutsideDestination(PathBuf::from("here/..")),
+            unsafe_reason(unpack(tar.as_slice(), &destination, 0))
+        );
+        assert!(!dir.path().join("evil").exists());
+
+        // `later` does not exist yet when `escape` is unpacked, it could become a symlink
+        let tar = archive(&[
+            Entry::Symlink("escape", "later/.."),
+            Entry::Symlink("later", "."),
+            Entry::File("escape/evil", ""),
+        ]);
+        assert_eq!(
+            UnsafeReason::LinkOutsideDestination(PathBuf::from("later/..")),
+            unsafe_reason(unpack(tar.as_slice(), &destination, 0))
+        );
+        assert!(!dir.path().join("evil").exists());
+    }
+}
@@ -0,0 +1,187 @@
+//! Tunes `bundle install` to the build machine
+//!
+//! `BUNDLE_JOBS` defaults to the number of CPUs available to the build, the lower of the CPUs
+//! the process may run on and the cgroup CPU quota of the container. `BUNDLE_RETRY` retries
+//! failed network requests. Both can be overridden with environment variables of the same name.
+//!
+//! They change how fast gems are installed and not which gems are installed, so they are left
+//! out of the gems layer digest and changing them does not re-run `bundle install`.
+use libcnb::Env;
+use std::num::NonZeroUsize;
+use std::path::Path;
+
+pub(crate) const BUNDLE_JOBS_ENV_KEY: &str = "BUNDLE_JOBS";
+pub(crate) const BUNDLE_RETRY_ENV_KEY: &str = "BUNDLE_RETRY";
+const DEFAULT_RETRY: &str = "3";
+const CGROUP_ROOT: &str = "/sys/fs/cgroup";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct BundleSetting {
+    pub(crate) key: &'static str,
+    pub(crate) value: String,
+    /// Where the value came from such as `BUNDLE_JOBS` or `4 CPUs available`
+    pub(crate) from: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct BundleParallelism {
+    pub(crate) jobs: BundleSetting,
+    pub(crate) retry: BundleSetting,
+}
+
+impl BundleParallelism {
+    /// Environment variables that are left out of the gems layer digest
+    pub(crate) const ENV_KEYS: [&'static str; 2] = [BUNDLE_JOBS_ENV_KEY, BUNDLE_RETRY_ENV_KEY];
+
+    pub(crate) fn detect(env: &Env) -> Self {
+        Self::from_env(env, available_cpus(Path::new(CGROUP_ROOT)))
+    }
+
+    fn from_env(env: &Env, cpus: usize) -> Self {
+        let setting = |key: &'static str, default: String, from: String| match env
+            .get_string_lossy(key)
+            .map(|value| value.trim().to_string())
+            .filter(|value| !value.is_empty())
+        {
+            Some(value) => BundleSetting {
+                key,
+                value,
+                from: key.to_string(),
+            },
+            None => BundleSetting {
+                key,
+                value: default,
+                from,
+            },
+        };
+        Self {
+            jobs: setting(
+                BUNDLE_JOBS_ENV_KEY,
+                cpus.to_string(),
+                format!(
+                    "{cpus} CPU{s} available",
+                    s = if cpus == 1 { "" } else { "s" }
+                ),
+            ),
+            retry: setting(
+                BUNDLE_RETRY_ENV_KEY,
+                String::from(DEFAULT_RETRY),
+                String::from("default"),
+            ),
+        }
+    }
+
+    /// Environment for the `bundle install` command
+    pub(crate) fn apply(&self, env: &Env) -> Env {
+        let mut env = env.clone();
+        for setting in [&self.jobs, &self.retry] {
+            env.insert(setting.key, &setting.value);
+        }
+        env
+    }
+}
+
+/// The lower of the CPUs the process may run on and the cgroup CPU quota, at least one
+fn available_cpus(cgroup_root: &Path) -> usize {
+    let cpus = std::thread::available_parallelism().map_or(1, NonZeroUsize::get);
+    cgroup_cpu_limit(cgroup_root)
+        .map_or(cpus, |limit| cpus.min(limit))
+        .max(1)
+}
+
+/// CPUs allowed by a cgroup v2 `cpu.max` or cgroup v1 `cpu.cfs_quota_us` quota, rounded up
+///
+/// Returns `None` when there is no quota or it cannot be read.
+fn cgroup_cpu_limit(root: &Path) -> Option<usize> {
+    let (quota, period) = if let Ok(max) = fs_err::read_to_string(root.join("cpu.max")) {
+        // i.e. `200000 100000`, or `max 100000` without a quota
+        let mut parts = max.split_whitespace();
+        (
+            parts.next()?.parse::<u64>().ok()?,
+            parts.next()?.parse::<u64>().ok()?,
+        )
+    } else {
+        let read = |file: &str| {
+            fs_err::read_to_string(root.join("cpu").join(file))
+                .ok()
+                .and_then(|contents| contents.trim().parse::<i64>().ok())
+                .and_then(|value| u64::try_from(value).ok())
+        };
+        // A quota of `-1` means no limit
+        (read("cpu.cfs_quota_us")?, read("cpu.cfs_period_us")?)
+    };
+    if quota == 0 || period == 0 {
+        return None;
+    }
+    usize::try_from(quota.div_ceil(period)).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_env() {
+        let mut env = Env::new();
+        let parallelism = BundleParallelism::from_env(&env, 4);
+        assert_eq!(
+            BundleSetting {
+                key: BUNDLE_JOBS_ENV_KEY,
+                value: String::from("4"),
+                from: String::from("4 CPUs available"),
+            },
+            parallelism.jobs
+        );
+        assert_eq!(
+            BundleSetting {
+                key: BUNDLE_RETRY_ENV_KEY,
+                value: String::from("3"),
+                from: String::from("default"),
+            },
+            parallelism.retry
+        );
+        assert_eq!(
+            "1 CPU available",
+            BundleParallelism::from_env(&env, 1).jobs.from
+        );
+
+        env.insert(BUNDLE_JOBS_ENV_KEY, " 2 ");
+        env.insert(BUNDLE_RETRY_ENV_KEY, "");
+        let parallelism = BundleParallelism::from_env(&env, 4);
+        assert_eq!("2", parallelism.jobs.value);
+        assert_eq!(BUNDLE_JOBS_ENV_KEY, parallelism.jobs.from);
+        assert_eq!("3", parallelism.retry.value);
+
+        let env = parallelism.apply(&Env::new());
+        assert_eq!(
+            Some(String::from("2")),
+            env.get_string_lossy(BUNDLE_JOBS_ENV_KEY)
+        );
+        assert_eq!(
+            Some(String::from("3")),
+            env.get_string_lossy(BUNDLE_RETRY_ENV_KEY)
+        );
+    }
+
+    #[test]
+    fn test_cgroup_cpu_limit() {
+        let root = tempfile::tempdir().unwrap();
+        assert_eq!(None, cgroup_cpu_limit(root.path()));
+        assert!(available_cpus(root.path()) >= 1);
+
+        fs_err::create_dir_all(root.path().join("cpu")).unwrap();
+        fs_err::write(root.path().join("cpu/cpu.cfs_quota_us"), "-1\n").unwrap();
+        fs_err::write(root.path().join("cpu/cpu.cfs_period_us"), "100000\n").unwrap();
+        assert_eq!(None, cgroup_cpu_limit(root.path()));
+
+        fs_err::write(root.path().join("cpu/cpu.cfs_quota_us"), "150000\n").unwrap();
+        assert_eq!(Some(2), cgroup_cpu_limit(root.path()));
+
+        fs_err::write(root.path().join("cpu.max"), "max 100000\n").unwrap();
+        assert_eq!(None, cgroup_cpu_limit(root.path()));
+
+        fs_err::write(root.path().join("cpu.max"), "50000 100000\n").unwrap();
+        assert_eq!(Some(1), cgroup_cpu_limit(root.path()));
+        assert_eq!(1, available_cpus(root.path()));
+    }
+}
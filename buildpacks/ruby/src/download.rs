@@ -0,0 +1,537 @@
+//! Download files over HTTP
+//!
+//! A single dropped connection or overloaded server should not fail a build. Requests are made
+//! with connect and read timeouts and failures that may be transient (connection errors, timeouts,
+//! `408`, `429`, and `5xx` responses) are retried with exponential backoff. When a connection drops
+//! part way through a download, the next attempt requests only the remaining bytes with an HTTP
+//! `Range` header. Servers that ignore the header send the whole file and the bytes that were
+//! already read are skipped.
+//!
+//! A `Download` is a `Read` so a response can be streamed into another reader, such as a
+//! decompressor, without writing it to disk first. Retries happen inside of `read` and are
+//! invisible to the consumer, they are recorded as events that can be shown once the download is
+//! done (see `Download::take_events`). A `file://` URL is read from the local disk without making a
+//! request.
+use byte_unit::{Byte, UnitType};
+use sha2::{Digest, Sha256};
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::Path;
+use std::time::Duration;
+use url::Url;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct RetryPolicy {
+    /// Total number of requests made, including the first
+    pub(crate) attempts: u32,
+    /// Wait before the first retry, doubled after every failed retry
+    pub(crate) initial_backoff: Duration,
+    pub(crate) max_backoff: Duration,
+    pub(crate) connect_timeout: Duration,
+    /// Maximum time to wait for data on an established connection
+    pub(crate) read_timeout: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            attempts: 5,
+            initial_backoff: Duration::from_secs(1),
+            max_backoff: Duration::from_secs(16),
+            connect_timeout: Duration::from_secs(10),
+            read_timeout: Duration::from_secs(30),
+        }
+    }
+}
+
+#[derive(thiserror::Error, Debug)]
+pub(crate) enum DownloadError {
+    #[error("Invalid download url {0}")]
+    InvalidUrl(String),
+
+    // Boxed to prevent `large_enum_variant` errors since `ureq::Error` is massive.
+    #[error("Could not download {url} after {attempts} {}: {error}", plural(*attempts))]
+    Request {
+        url: String,
+        attempts: u32,
+        error: Box<ureq::Error>,
+    },
+
+    #[error("Could not download {url} after {attempts} {}, the connection failed: {error}", plural(*attempts))]
+    Interrupted {
+        url: String,
+        attempts: u32,
+        error: io::Error,
+    },
+
+    #[error("Could not read {url}: {error}")]
+    CouldNotReadFile { url: String, error: io::Error },
+
+    #[error("Could not create file: {0}")]
+    CouldNotCreateDestinationFile(io::Error),
+
+    #[error("Could not write file: {0}")]
+    CouldNotWriteDestinationFile(io::Error),
+}
+
+fn plural(attempts: u32) -> &'static str {
+    if attempts == 1 {
+        "attempt"
+    } else {
+        "attempts"
+    }
+}
+
+/// Download `url` to `destination` using the default `RetryPolicy`
+pub(crate) fn download(url: &str, destination: &Path) -> Result<(), DownloadError> {
+    download_with(&RetryPolicy::default(), url, destination)
+}
+
+pub(crate) fn download_with(
+    policy: &RetryPolicy,
+    url: &str,
+    destination: &Path,
+) -> Result<(), DownloadError> {
+    let mut download = Download::start_with(policy, url)?;
+    let mut file =
+        fs_err::File::create(destination).map_err(DownloadError::CouldNotCreateDestinationFile)?;
+
+    io::copy(&mut download, &mut file).map_err(|error| {
+        download
+            .take_error()
+            .unwrap_or(DownloadError::CouldNotWriteDestinationFile(error))
+    })?;
+    Ok(())
+}
+
+/// A download in progress, read it to receive the response body
+pub(crate) struct Download {
+    url: Url,
+    policy: RetryPolicy,
+    agent: ureq::Agent,
+    reader: Box<dyn Read + Send + Sync>,
+    content_length: Option<u64>,
+    bytes_read: u64,
+    attempts: u32,
+    backoff: Duration,
+    error: Option<DownloadError>,
+    events: Vec<String>,
+}
+
+impl Download {
+    /// Makes the first request using the default `RetryPolicy`, retrying until a response is received
+    pub(crate) fn start(url: &str) -> Result<Self, DownloadError> {
+        Self::start_with(&RetryPolicy::default(), url)
+    }
+
+    pub(crate) fn start_with(policy: &RetryPolicy, url: &str) -> Result<Self, DownloadError> {
+        let mut download = Self {
+            url: Url::parse(url).map_err(|_| DownloadError::InvalidUrl(url.to_string()))?,
+            policy: policy.clone(),
+            agent: ureq::AgentBuilder::new()
+                .timeout_connect(policy.connect_timeout)
+                .timeout_read(policy.read_timeout)
+                .build(),
+            reader: Box::new(io::empty()),
+            content_length: None,
+            bytes_read: 0,
+            attempts: 1,
+            backoff: policy.initial_backoff,
+            error: None,
+            events: Vec::new(),
+        };
+        download.connect()?;
+        Ok(download)
+    }
+
+    /// Size of the download when reported by the server
+    pub(crate) fn content_length(&self) -> Option<u64> {
+        self.content_length
+    }
+
+    /// The error that caused a `read` to fail
+    ///
+    /// `Read` can only return an `io::Error`, this returns the full error including how many
+    /// attempts were made.
+    pub(crate) fn take_error(&mut self) -> Option<DownloadError> {
+        self.error.take()
+    }
+
+    /// Retries and resumed requests since the last call, one message per event
+    pub(crate) fn take_events(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.events)
+    }
+
+    fn connect(&mut self) -> Result<(), DownloadError> {
+        loop {
+            match self.request() {
+                Ok(reader) => {
+                    self.reader = reader;
+                    return Ok(());
+                }
+                Err(error) if error.is_retryable() && self.attempts < self.policy.attempts => {
+                    self.retry(&error);
+                }
+                Err(error) => return Err(error.into_download_error(&self.url, self.attempts)),
+            }
+        }
+    }
+
+    fn retry(&mut self, error: &AttemptError) {
+        self.events.push(format!(
+            "Attempt {attempt} of {attempts} failed ({error}), retrying in {backoff:?}",
+            attempt = self.attempts,
+            attempts = self.policy.attempts,
+            backoff = self.backoff,
+        ));
+        std::thread::sleep(self.backoff);
+        self.backoff = (self.backoff * 2).min(self.policy.max_backoff);
+        self.attempts += 1;
+    }
+
+    /// Makes a single request for the bytes that have not been read yet
+    fn request(&mut self) -> Result<Box<dyn Read + Send + Sync>, AttemptError> {
+        if self.url.scheme() == "file" {
+            let path = self
+                .url
+                .to_file_path()
+                .map_err(|()| AttemptError::File(io::Error::other("not a local path")))?;
+            let mut file = fs_err::File::open(path).map_err(AttemptError::File)?;
+            if self.content_length.is_none() {
+                self.content_length = file.metadata().map(|metadata| metadata.len()).ok();
+            }
+            file.seek(SeekFrom::Start(self.bytes_read))
+                .map_err(AttemptError::File)?;
+            return Ok(Box::new(file));
+        }
+
+        let mut request = self.agent.get(self.url.as_str());
+        if self.bytes_read > 0 {
+            request = request.set("Range", &format!("bytes={}-", self.bytes_read));
+        }
+        let response = request
+            .call()
+            .map_err(|error| AttemptError::Request(Box::new(error)))?;
+        if self.content_length.is_none() {
+            self.content_length = response
+                .header("Content-Length")
+                .and_then(|length| length.parse().ok());
+        }
+
+        let status = response.status();
+        let mut reader = response.into_reader();
+        if self.bytes_read > 0 {
+            if status == 206 {
+                self.events.push(format!(
+                    "Resuming download after {}",
+                    human_bytes(u128::from(self.bytes_read))
+                ));
+            } else {
+                // The server ignored the range and is sending the whole file
+                self.events.push(format!(
+                    "Server does not support resuming, skipping the first {}",
+                    human_bytes(u128::from(self.bytes_read))
+                ));
+                io::copy(&mut (&mut reader).take(self.bytes_read), &mut io::sink())
+                    .map_err(AttemptError::Interrupted)?;
+            }
+        }
+        Ok(reader)
+    }
+}
+
+impl Read for Download {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            let error = match self.reader.read(buf) {
+                Ok(read) => {
+                    self.bytes_read += read as u64;
+                    return Ok(read);
+                }
+                Err(error) if error.kind() == io::ErrorKind::Interrupted => continue,
+                Err(error) => {
+                    let error = if self.url.scheme() == "file" {
+                        AttemptError::File(error)
+                    } else {
+                        AttemptError::Interrupted(error)
+                    };
+                    if error.is_retryable() && self.attempts < self.policy.attempts {
+                        self.retry(&error);
+                        match self.connect() {
+                            Ok(()) => continue,
+                            Err(error) => error,
+                        }
+                    } else {
+                        error.into_download_error(&self.url, self.attempts)
+                    }
+                }
+            };
+            let io_error = io::Error::other(error.to_string());
+            self.error = Some(error);
+            return Err(io_error);
+        }
+    }
+}
+
+/// Computes the sha256 of everything read through it
+pub(crate) struct Sha256Reader<R> {
+    pub(crate) inner: R,
+    hasher: Sha256,
+}
+
+impl<R> Sha256Reader<R> {
+    pub(crate) fn new(inner: R) -> Self {
+        Self {
+            inner,
+            hasher: Sha256::new(),
+        }
+    }
+
+    /// Lowercase hex sha256 of the bytes read so far
+    pub(crate) fn hex_digest(&self) -> String {
+        format!("{:x}", self.hasher.clone().finalize())
+    }
+}
+
+impl<R: Read> Read for Sha256Reader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.hasher.update(&buf[..read]);
+        Ok(read)
+    }
+}
+
+pub(crate) fn human_bytes(bytes: u128) -> String {
+    format!(
+        "{:.1}",
+        Byte::from_u128(bytes)
+            .unwrap_or(Byte::MAX)
+            .get_appropriate_unit(UnitType::Binary)
+    )
+}
+
+/// A failed attempt that may be retried
+enum AttemptError {
+    Request(Box<ureq::Error>),
+    Interrupted(io::Error),
+    File(io::Error),
+}
+
+impl std::fmt::Display for AttemptError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AttemptError::Request(error) => write!(f, "{error}"),
+            AttemptError::Interrupted(error) => write!(f, "the connection failed: {error}"),
+            AttemptError::File(error) => write!(f, "{error}"),
+        }
+    }
+}
+
+impl AttemptError {
+    fn is_retryable(&self) -> bool {
+        match self {
+            AttemptError::Request(error) => match error.as_ref() {
+                ureq::Error::Status(status, _) => matches!(status, 408 | 429 | 500..=599),
+                ureq::Error::Transport(_) => true,
+            },
+            AttemptError::Interrupted(_) => true,
+            AttemptError::File(_) => false,
+        }
+    }
+
+    fn into_download_error(self, url: &Url, attempts: u32) -> DownloadError {
+        let url = url.to_string();
+        match self {
+            AttemptError::Request(error) => DownloadError::Request {
+                url,
+                attempts,
+                error,
+            },
+            AttemptError::Interrupted(error) => DownloadError::Interrupted {
+                url,
+                attempts,
+                error,
+            },
+            AttemptError::File(error) => DownloadError::CouldNotReadFile { url, error },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, Write};
+    use std::net::TcpListener;
+    use std::thread::JoinHandle;
+
+    fn policy() -> RetryPolicy {
+        RetryPolicy {
+            attempts: 3,
+            initial_backoff: Duration::ZERO,
+            max_backoff: Duration::ZERO,
+            connect_timeout: Duration::from_secs(5),
+            read_timeout: Duration::from_secs(5),
+        }
+    }
+
+    /// Serves one raw HTTP response per connection, returns the request headers received
+    fn serve(responses: Vec<Vec<u8>>) -> (String, JoinHandle<Vec<String>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/ruby.tgz", listener.local_addr().unwrap());
+        let handle = std::thread::spawn(move || {
+            responses
+                .into_iter()
+                .map(|response| {
+                    let (mut stream, _) = listener.accept().unwrap();
+                    let mut reader = io::BufReader::new(stream.try_clone().unwrap());
+                    let mut request = String::new();
+                    loop {
+                        let mut line = String::new();
+                        reader.read_line(&mut line).unwrap();
+                        if line.trim().is_empty() {
+                            break;
+                        }
+                        request.push_str(&line);
+                    }
+                    stream.write_all(&response).unwrap();
+                    request
+                })
+                .collect()
+        });
+        (url, handle)
+    }
+
+    fn response(status: &str, headers: &str, body: &str) -> Vec<u8> {
+        format!("HTTP/1.1 {status}\r\nConnection: close\r\n{headers}\r\n{body}").into_bytes()
+    }
+
+    #[test]
+    fn test_retries_server_errors() {
+        let (url, server) = serve(vec![
+            response("503 Service Unavailable", "Content-Length: 0\r\n", ""),
+            response("200 OK", "Content-Length: 5\r\n", "hello"),
+        ]);
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("ruby.tgz");
+
+        let mut download = Download::start_with(&policy(), &url).unwrap();
+        io::copy(&mut download, &mut fs_err::File::create(&path).unwrap()).unwrap();
+        assert_eq!("hello", fs_err::read_to_string(&path).unwrap());
+        assert_eq!(2, server.join().unwrap().len());
+
+        let events = download.take_events();
+        assert_eq!(1, events.len(), "{events:?}");
+        assert!(
+            events[0].starts_with("Attempt 1 of 3 failed (") && events[0].contains("503"),
+            "{events:?}"
+        );
+    }
+
+    #[test]
+    fn test_resumes_interrupted_download() {
+        let (url, server) = serve(vec![
+            response("200 OK", "Content-Length: 10\r\n", "hello"),
+            response(
+                "206 Partial Content",
+                "Content-Length: 5\r\nContent-Range: bytes 5-9/10\r\n",
+                "world",
+            ),
+        ]);
+        let mut download = Download::start_with(&policy(), &url).unwrap();
+        let mut body = String::new();
+        download.read_to_string(&mut body).unwrap();
+        assert_eq!("helloworld", body);
+        assert_eq!(
+            Some("Resuming download after 5 B"),
+            download.take_events().last().map(String::as_str)
+        );
+
+        let requests = server.join().unwrap();
+        assert!(!requests[0].to_lowercase().contains("range:"));
+        assert!(
+            requests[1].to_lowercase().contains("range: bytes=5-"),
+            "{}",
+            requests[1]
+        );
+    }
+
+    #[test]
+    fn test_range_ignored_restarts_download() {
+        let (url, server) = serve(vec![
+            response("200 OK", "Content-Length: 10\r\n", "hello"),
+            response("200 OK", "Content-Length: 10\r\n", "helloworld"),
+        ]);
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("ruby.tgz");
+
+        download_with(&policy(), &url, &path).unwrap();
+        assert_eq!("helloworld", fs_err::read_to_string(&path).unwrap());
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn test_attempts_in_error() {
+        let (url, server) = serve(vec![
+            response("500 Internal Server Error", "Content-Length: 0\r\n", ""),
+            response("500 Internal Server Error", "Content-Length: 0\r\n", ""),
+            response("500 Internal Server Error", "Content-Length: 0\r\n", ""),
+        ]);
+        let dir = tempfile::tempdir().unwrap();
+        let error = download_with(&policy(), &url, &dir.path().join("ruby.tgz")).unwrap_err();
+
+        assert!(error.to_string().contains("after 3 attempts"), "{error}");
+        assert_eq!(3, server.join().unwrap().len());
+    }
+
+    #[test]
+    fn test_stream_resumes_interrupted_download() {
+        let (url, server) = serve(vec![
+            response("200 OK", "Content-Length: 10\r\n", "hello"),
+            response("200 OK", "Content-Length: 10\r\n", "helloworld"),
+        ]);
+        let mut download = Download::start_with(&policy(), &url).unwrap();
+        assert_eq!(Some(10), download.content_length());
+
+        let mut body = String::new();
+        download.read_to_string(&mut body).unwrap();
+        assert_eq!("helloworld", body);
+        assert_eq!(10, download.bytes_read);
+        server.join().unwrap();
+
+        let events = download.take_events();
+        assert_eq!(2, events.len(), "{events:?}");
+        assert!(events[0].starts_with("Attempt 1 of 3 failed (the connection failed:"));
+        assert_eq!(
+            "Server does not support resuming, skipping the first 5 B",
+            events[1]
+        );
+        assert!(download.take_events().is_empty());
+    }
+
+    #[test]
+    fn test_file_url() {
+        let dir = tempfile::tempdir().unwrap();
+        let source = dir.path().join("ruby-3.3.6.tgz");
+        let destination = dir.path().join("download.tgz");
+        fs_err::write(&source, "tarball").unwrap();
+
+        let url = Url::from_file_path(&source).unwrap();
+        download_with(&policy(), url.as_str(), &destination).unwrap();
+        assert_eq!("tarball", fs_err::read_to_string(&destination).unwrap());
+
+        let missing = Url::from_file_path(dir.path().join("missing.tgz")).unwrap();
+        assert!(matches!(
+            download_with(&policy(), missing.as_str(), &destination),
+            Err(DownloadError::CouldNotReadFile { .. })
+        ));
+    }
+
+    #[test]
+    fn test_client_errors_are_not_retried() {
+        let (url, server) = serve(vec![response("404 Not Found", "Content-Length: 0\r\n", "")]);
+        let dir = tempfile::tempdir().unwrap();
+        let error = download_with(&policy(), &url, &dir.path().join("ruby.tgz")).unwrap_err();
+
+        assert!(error.to_string().contains("after 1 attempt:"), "{error}");
+        assert_eq!(1, server.join().unwrap().len());
+    }
+}
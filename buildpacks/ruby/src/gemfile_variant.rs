@@ -0,0 +1,82 @@
+use fs_err::PathExt;
+use std::path::Path;
+
+/// Bundler supports two naming conventions for an application's dependency files
+///
+/// - `Gemfile` and `Gemfile.lock`
+/// - `gems.rb` and `gems.locked`
+///
+/// When both are present, bundler prefers `gems.rb`, we do the same.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum GemfileVariant {
+    Gemfile,
+    GemsRb,
+}
+
+impl GemfileVariant {
+    /// Returns the variant used by the application, if any
+    ///
+    /// A variant is selected when either its Gemfile or its lockfile is present.
+    ///
+    /// # Errors
+    ///
+    /// Errors if the existence of a file cannot be determined.
+    pub(crate) fn from_dir(app_dir: &Path) -> Result<Option<Self>, std::io::Error> {
+        for variant in [Self::GemsRb, Self::Gemfile] {
+            if app_dir.join(variant.gemfile()).fs_err_try_exists()?
+                || app_dir.join(variant.lockfile()).fs_err_try_exists()?
+            {
+                return Ok(Some(variant));
+            }
+        }
+        Ok(None)
+    }
+
+    pub(crate) fn gemfile(self) -> &'static str {
+        match self {
+            Self::Gemfile => "Gemfile",
+            Self::GemsRb => "gems.rb",
+        }
+    }
+
+    pub(crate) fn lockfile(self) -> &'static str {
+        match self {
+            Self::Gemfile => "Gemfile.lock",
+            Self::GemsRb => "gems.locked",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_no_variant() {
+        let dir = tempfile::tempdir().unwrap();
+        assert_eq!(None, GemfileVariant::from_dir(dir.path()).unwrap());
+    }
+
+    #[test]
+    fn test_gemfile_variant() {
+        let dir = tempfile::tempdir().unwrap();
+        fs_err::write(dir.path().join("Gemfile.lock"), "").unwrap();
+
+        assert_eq!(
+            Some(GemfileVariant::Gemfile),
+            GemfileVariant::from_dir(dir.path()).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_gems_rb_variant_preferred() {
+        let dir = tempfile::tempdir().unwrap();
+        fs_err::write(dir.path().join("Gemfile"), "").unwrap();
+        fs_err::write(dir.path().join("gems.rb"), "").unwrap();
+
+        let variant = GemfileVariant::from_dir(dir.path()).unwrap().unwrap();
+        assert_eq!(GemfileVariant::GemsRb, variant);
+        assert_eq!("gems.rb", variant.gemfile());
+        assert_eq!("gems.locked", variant.lockfile());
+    }
+}
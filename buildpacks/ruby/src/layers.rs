@@ -1,5 +1,9 @@
 pub(crate) mod bundle_download_layer;
 pub(crate) mod bundle_install_layer;
 pub(crate) mod metrics_agent_install;
+pub(crate) mod ruby_cache_layer;
 pub(crate) mod ruby_install_layer;
 mod shared;
+
+#[cfg(test)]
+pub(crate) use shared::temp_build_context;
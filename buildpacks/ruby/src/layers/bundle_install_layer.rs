@@ -4,7 +4,7 @@
 //! to execute on every build (as opposed to only when the cache is empty).
 //!
 //! As a small performance optimization, it will not run if the `Gemfile.lock`,
-//! `Gemfile`, or user provided "platform" environment variable have not changed.
+//! `Gemfile`, gem groups, or user provided "platform" environment variable have not changed.
 //! User applications can opt out of this behavior by setting the environment
 //! variable `HEROKU_SKIP_BUNDLE_DIGEST=1`. That would be useful if the application's
 //! `Gemfile` sources logic or data from another file that is unknown to the buildpack.
@@ -12,22 +12,28 @@
 //! Gems can be plain Ruby code which are OS, Architecture, and Ruby version independent.
 //! They can also be native extensions that use Ruby's C API or contain libraries that
 //! must be compiled and will then be invoked via FFI. These native extensions are
-//! OS, Architecture, and Ruby version dependent. Due to this, when one of these changes
+//! OS, Architecture, Ruby engine, and Ruby version dependent. Due to this, when one of these changes
 //! we must clear the cache and re-run `bundle install`.
+use crate::bundle_parallelism::BundleParallelism;
+use crate::gem_credentials::GemCredentials;
 use crate::layers::shared::{cached_layer_write_metadata, Meta, MetadataDiff};
+use crate::project_config::{ProjectConfig, PROJECT_TOML};
 use crate::target_id::{TargetId, TargetIdError};
-use crate::{BundleWithout, RubyBuildpack, RubyBuildpackError};
+use crate::{DetectError, RubyBuildpack, RubyBuildpackError};
 use bullet_stream::state::SubBullet;
 use bullet_stream::{style, Print};
 use commons::{
-    display::SentenceList, gemfile_lock::ResolvedRubyVersion, metadata_digest::MetadataDigest,
+    display::SentenceList,
+    gemfile::Gemfile,
+    gemfile_lock::{ResolvedRuby, ResolvedRubyVersion, RubyEngine},
+    metadata_digest::MetadataDigest,
 };
 use fun_run::{self, CommandWithName};
 use libcnb::data::layer_name;
 use libcnb::layer::{EmptyLayerCause, LayerState};
 use libcnb::{
     layer_env::{LayerEnv, ModificationBehavior, Scope},
-    Env,
+    Env, Platform,
 };
 use magic_migrate::{try_migrate_deserializer_chain, TryMigrate};
 use serde::{Deserialize, Deserializer, Serialize};
@@ -44,12 +50,130 @@ const SKIP_DIGEST_ENV_KEY: &str = "HEROKU_SKIP_BUNDLE_DIGEST";
 /// on the next build.
 pub(crate) const FORCE_BUNDLE_INSTALL_CACHE_KEY: &str = "v1";
 
+pub(crate) const BUNDLE_WITHOUT_ENV_KEY: &str = "BUNDLE_WITHOUT";
+/// Gem groups left out of `bundle install` when not configured
+const DEFAULT_WITHOUT: &str = "development:test";
+
+#[derive(serde::Deserialize, serde::Serialize, Debug, Clone, PartialEq, Eq)]
+pub(crate) struct BundleWithout(String);
+
+impl BundleWithout {
+    pub(crate) fn new(without: impl AsRef<str>) -> Self {
+        Self(String::from(without.as_ref()))
+    }
+    pub(crate) fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    /// Groups left out of `bundle install` and where they came from
+    ///
+    /// `BUNDLE_WITHOUT` from the environment takes precedence over `bundle_without` in
+    /// `project.toml`, the default leaves out `development` and `test`.
+    fn from_config(env: &libcnb::Env, config: &ProjectConfig) -> (Self, &'static str) {
+        match env.get_string_lossy(BUNDLE_WITHOUT_ENV_KEY) {
+            Some(value) if !value.trim().is_empty() => {
+                (Self::new(value.trim()), BUNDLE_WITHOUT_ENV_KEY)
+            }
+            _ => config.bundle_without.as_ref().map_or_else(
+                || (Self::new(DEFAULT_WITHOUT), "default"),
+                |groups| (Self::new("").with(groups), PROJECT_TOML),
+            ),
+        }
+    }
+
+    /// Bundler accepts groups separated by `:` or spaces
+    fn groups(&self) -> impl Iterator<Item = &str> {
+        self.0.split([':', ' ']).filter(|group| !group.is_empty())
+    }
+
+    fn contains(&self, group: &str) -> bool {
+        self.groups().any(|name| name == group)
+    }
+
+    /// Also leaves out `groups`
+    fn with(&self, groups: &[String]) -> Self {
+        let mut without = self.clone();
+        for group in groups.iter().map(|group| group.trim()) {
+            if !group.is_empty() && !without.contains(group) {
+                if !without.0.is_empty() {
+                    without.0.push(':');
+                }
+                without.0.push_str(group);
+            }
+        }
+        without
+    }
+}
+
+/// Gem groups left out of `bundle install` and left out at launch
+///
+/// Build-only groups are installed for the build and added to `BUNDLE_WITHOUT` at launch. Warns
+/// when a build-only group is also left out of the install, or has no gems in the `Gemfile`.
+pub(crate) fn gem_groups(
+    mut bullet: Print<SubBullet<Stdout>>,
+    env: &Env,
+    config: &ProjectConfig,
+    gemfile: &Path,
+) -> Result<(Print<SubBullet<Stdout>>, BundleWithout, BundleWithout), RubyBuildpackError> {
+    let (without, without_from) = BundleWithout::from_config(env, config);
+    let build_only = config.build_only_groups.clone().unwrap_or_default();
+    bullet = bullet.sub_bullet(format!(
+        "Without groups {} from {}",
+        style::value(without.as_str()),
+        style::value(without_from)
+    ));
+    for group in build_only.iter().filter(|group| without.contains(group)) {
+        bullet = bullet.warning(format!(
+            "Build-only group {group} is also excluded with {without} and will not be \
+             installed. Remove it from {bundle_without} or {build_only_groups}.",
+            group = style::value(group),
+            without = style::value(without.as_str()),
+            bundle_without = style::value(BUNDLE_WITHOUT_ENV_KEY),
+            build_only_groups = style::value("build_only_groups"),
+        ));
+    }
+    if !build_only.is_empty() {
+        let parsed = fs_err::read_to_string(gemfile)
+            .map(|contents| Gemfile::parse(&contents))
+            .map_err(DetectError::Gemfile)
+            .map_err(RubyBuildpackError::BuildpackDetectionError)?;
+        // Groups can be declared in statements that cannot be read statically
+        if parsed.is_complete() {
+            for group in &build_only {
+                if parsed.gems_in_group(group.trim()).next().is_none() {
+                    bullet = bullet.warning(format!(
+                        "Build-only group {group} has no gems in {gemfile}",
+                        group = style::value(group),
+                        gemfile = style::value(
+                            gemfile
+                                .file_name()
+                                .unwrap_or(gemfile.as_os_str())
+                                .to_string_lossy()
+                        ),
+                    ));
+                }
+            }
+        }
+    }
+    let launch_without = without.with(&build_only);
+    if launch_without != without {
+        bullet = bullet.sub_bullet(format!(
+            "Installing build-only groups for the build, launch uses {}",
+            style::value(format!(
+                "{BUNDLE_WITHOUT_ENV_KEY}={}",
+                launch_without.as_str()
+            ))
+        ));
+    }
+    Ok((bullet, without, launch_without))
+}
+
 pub(crate) fn handle(
     context: &libcnb::build::BuildContext<RubyBuildpack>,
     env: &Env,
     mut bullet: Print<SubBullet<Stdout>>,
     metadata: &Metadata,
-    without: &BundleWithout,
+    gemfile: &Path,
 ) -> libcnb::Result<(Print<SubBullet<Stdout>>, LayerEnv), RubyBuildpackError> {
     let layer_ref = cached_layer_write_metadata(layer_name!("gems"), context, metadata)?;
     let install_state = match &layer_ref.state {
@@ -71,7 +195,12 @@ pub(crate) fn handle(
     };
 
     let env = {
-        let layer_env = layer_env(&layer_ref.path(), &context.app_dir, without);
+        let layer_env = layer_env(
+            &layer_ref.path(),
+            gemfile,
+            &metadata.without,
+            &metadata.launch_without,
+        );
         layer_ref.write_env(&layer_env)?;
         layer_env.apply(Scope::Build, env)
     };
@@ -82,15 +211,43 @@ pub(crate) fn handle(
                 bullet = bullet.sub_bullet(reason);
             }
 
+            let parallelism = BundleParallelism::detect(&env);
+            bullet = bullet.sub_bullet(format!(
+                "Using {jobs} ({jobs_from}) and {retry} ({retry_from})",
+                jobs = style::value(format!(
+                    "{}={}",
+                    parallelism.jobs.key, parallelism.jobs.value
+                )),
+                jobs_from = parallelism.jobs.from,
+                retry = style::value(format!(
+                    "{}={}",
+                    parallelism.retry.key, parallelism.retry.value
+                )),
+                retry_from = parallelism.retry.from,
+            ));
+            let env = parallelism.apply(&env);
+            // Gem source credentials are removed from the build env, only this command gets them
+            let credentials = GemCredentials::from_env(context.platform.env());
+            let env = credentials.apply(&env);
+            let keys = credentials.keys().map(style::value).collect::<Vec<_>>();
+            if !keys.is_empty() {
+                bullet = bullet.sub_bullet(format!(
+                    "Using gem source credentials from {} (values hidden)",
+                    SentenceList::new(&keys).join_str("and")
+                ));
+            }
+
             let mut cmd = Command::new("bundle");
             cmd.args(["install"])
                 .env_clear() // Current process env vars already merged into env
                 .envs(&env);
-            let mut cmd = cmd.named_fn(|cmd| display_name(cmd, &env));
+            let mut cmd = cmd.named_fn(|cmd| display_name(cmd, &env, &credentials));
             bullet
                 .stream_with(
                     format!("Running {}", style::command(cmd.name())),
-                    |stdout, stderr| cmd.stream_output(stdout, stderr),
+                    |stdout, stderr| {
+                        cmd.stream_output(credentials.writer(stdout), credentials.writer(stderr))
+                    },
                 )
                 .map_err(|error| {
                     fun_run::map_which_problem(error, cmd.mut_cmd(), env.get("PATH").cloned())
@@ -116,13 +273,23 @@ pub(crate) fn handle(
     Ok((bullet, layer_ref.read_env()?))
 }
 
-pub(crate) type Metadata = MetadataV2;
+pub(crate) type Metadata = MetadataV4;
 try_migrate_deserializer_chain!(
-    chain: [MetadataV1, MetadataV2],
+    chain: [MetadataV1, MetadataV2, MetadataV3, MetadataV4],
     error: MetadataMigrateError,
     deserializer: toml::Deserializer::new,
 );
 
+impl MetadataV4 {
+    pub(crate) fn ruby(&self) -> ResolvedRuby {
+        ResolvedRuby {
+            engine: self.ruby_engine,
+            engine_version: self.ruby_engine_version.clone(),
+            version: self.ruby_version.clone(),
+        }
+    }
+}
+
 impl MetadataDiff for Metadata {
     fn diff(&self, old: &Self) -> Vec<String> {
         let mut differences = Vec::new();
@@ -130,16 +297,20 @@ impl MetadataDiff for Metadata {
             distro_name,
             distro_version,
             cpu_architecture,
-            ruby_version,
+            ruby_version: _,
+            ruby_engine: _,
+            ruby_engine_version: _,
             force_bundle_install_key: _,
+            without: _,
+            launch_without: _,
             digest: _,
         } = old;
 
-        if ruby_version != &self.ruby_version {
+        if old.ruby() != self.ruby() {
             differences.push(format!(
                 "Ruby version ({old} to {now})",
-                old = style::value(ruby_version.to_string()),
-                now = style::value(self.ruby_version.to_string())
+                old = style::value(old.ruby().to_string()),
+                now = style::value(self.ruby().to_string())
             ));
         }
         if distro_name != &self.distro_name || distro_version != &self.distro_version {
@@ -191,6 +362,58 @@ pub(crate) struct MetadataV2 {
     pub(crate) digest: MetadataDigest, // Must be last for serde to be happy https://github.com/toml-rs/toml-rs/issues/142
 }
 
+#[derive(Deserialize, Serialize, Debug, Clone, Eq, PartialEq)]
+pub(crate) struct MetadataV3 {
+    pub(crate) distro_name: String,
+    pub(crate) distro_version: String,
+    pub(crate) cpu_architecture: String,
+    pub(crate) ruby_version: ResolvedRubyVersion,
+    pub(crate) ruby_engine: RubyEngine,
+    pub(crate) ruby_engine_version: String,
+    pub(crate) force_bundle_install_key: String,
+
+    /// A struct that holds the cryptographic hash of components that can
+    /// affect the result of `bundle install`. When these values do not
+    /// change between deployments we can skip re-running `bundle install` since
+    /// the outcome should not change.
+    ///
+    /// While a fully resolved `bundle install` is relatively fast, it's not
+    /// instantaneous. This check can save ~1 second on overall build time.
+    ///
+    /// This value is cached with metadata, so changing the struct
+    /// may cause metadata to be invalidated (and the cache cleared).
+    ///
+    pub(crate) digest: MetadataDigest, // Must be last for serde to be happy https://github.com/toml-rs/toml-rs/issues/142
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Eq, PartialEq)]
+pub(crate) struct MetadataV4 {
+    pub(crate) distro_name: String,
+    pub(crate) distro_version: String,
+    pub(crate) cpu_architecture: String,
+    pub(crate) ruby_version: ResolvedRubyVersion,
+    pub(crate) ruby_engine: RubyEngine,
+    pub(crate) ruby_engine_version: String,
+    pub(crate) force_bundle_install_key: String,
+    /// Gem groups left out of `bundle install`
+    pub(crate) without: BundleWithout,
+    /// Gem groups left out at launch, includes build-only groups
+    pub(crate) launch_without: BundleWithout,
+
+    /// A struct that holds the cryptographic hash of components that can
+    /// affect the result of `bundle install`. When these values do not
+    /// change between deployments we can skip re-running `bundle install` since
+    /// the outcome should not change.
+    ///
+    /// While a fully resolved `bundle install` is relatively fast, it's not
+    /// instantaneous. This check can save ~1 second on overall build time.
+    ///
+    /// This value is cached with metadata, so changing the struct
+    /// may cause metadata to be invalidated (and the cache cleared).
+    ///
+    pub(crate) digest: MetadataDigest, // Must be last for serde to be happy https://github.com/toml-rs/toml-rs/issues/142
+}
+
 #[derive(thiserror::Error, Debug)]
 pub(crate) enum MetadataMigrateError {
     #[error("Could not migrate metadata {0}")]
@@ -217,6 +440,41 @@ impl TryFrom<MetadataV1> for MetadataV2 {
     }
 }
 
+// Versions prior to V3 stored JRuby versions as `<version>-jruby-<engine_version>`
+impl From<MetadataV2> for MetadataV3 {
+    fn from(v2: MetadataV2) -> Self {
+        let ruby = ResolvedRuby::from_legacy_version(&v2.ruby_version);
+        Self {
+            distro_name: v2.distro_name,
+            distro_version: v2.distro_version,
+            cpu_architecture: v2.cpu_architecture,
+            ruby_version: ruby.version,
+            ruby_engine: ruby.engine,
+            ruby_engine_version: ruby.engine_version,
+            force_bundle_install_key: v2.force_bundle_install_key,
+            digest: v2.digest,
+        }
+    }
+}
+
+// Versions prior to V4 always left out the `development` and `test` groups
+impl From<MetadataV3> for MetadataV4 {
+    fn from(v3: MetadataV3) -> Self {
+        Self {
+            distro_name: v3.distro_name,
+            distro_version: v3.distro_version,
+            cpu_architecture: v3.cpu_architecture,
+            ruby_version: v3.ruby_version,
+            ruby_engine: v3.ruby_engine,
+            ruby_engine_version: v3.ruby_engine_version,
+            force_bundle_install_key: v3.force_bundle_install_key,
+            without: BundleWithout::new(DEFAULT_WITHOUT),
+            launch_without: BundleWithout::new(DEFAULT_WITHOUT),
+            digest: v3.digest,
+        }
+    }
+}
+
 #[derive(Debug)]
 enum InstallState {
     /// Holds message indicating the reason why we want to run 'bundle install'
@@ -241,6 +499,12 @@ fn install_state(old: &Metadata, now: &Metadata) -> InstallState {
         let value = value.to_string_lossy();
 
         InstallState::Run(format!("found {SKIP_DIGEST_ENV_KEY}={value}"))
+    } else if old.without != now.without || old.launch_without != now.launch_without {
+        InstallState::Run(format!(
+            "Gem groups changed ({old} to {now})",
+            old = style::value(groups_description(old)),
+            now = style::value(groups_description(now))
+        ))
     } else if let Some(changed) = now.digest.changed(&old.digest) {
         InstallState::Run(format!("{changed}"))
     } else {
@@ -249,7 +513,26 @@ fn install_state(old: &Metadata, now: &Metadata) -> InstallState {
     }
 }
 
-fn layer_env(layer_path: &Path, app_dir: &Path, without_default: &BundleWithout) -> LayerEnv {
+fn groups_description(metadata: &Metadata) -> String {
+    if metadata.without == metadata.launch_without {
+        format!("{BUNDLE_WITHOUT_ENV_KEY}={}", metadata.without.as_str())
+    } else {
+        format!(
+            "{BUNDLE_WITHOUT_ENV_KEY}={}, at launch {}",
+            metadata.without.as_str(),
+            metadata.launch_without.as_str()
+        )
+    }
+}
+
+/// Build-only gem groups are in `launch_without` but not in `without`, they are installed for the
+/// build and not loaded by bundler at launch
+fn layer_env(
+    layer_path: &Path,
+    gemfile: &Path,
+    without: &BundleWithout,
+    launch_without: &BundleWithout,
+) -> LayerEnv {
     // CAREFUL: See environment variable warning below vvvvvvvvvv
     let layer_env = LayerEnv::new()
         .chainable_insert(
@@ -271,17 +554,11 @@ fn layer_env(layer_path: &Path, app_dir: &Path, without_default: &BundleWithout)
             "GEM_PATH", // Tells Ruby where gems are located. Should match `BUNDLE_PATH`.
             layer_path,
         )
-        .chainable_insert(
-            Scope::All,
-            ModificationBehavior::Default,
-            "BUNDLE_WITHOUT", // Do not install `development` or `test` groups via bundle install. Additional groups can be specified via user config.
-            without_default.as_str(),
-        )
         .chainable_insert(
             Scope::All,
             ModificationBehavior::Override,
-            "BUNDLE_GEMFILE", // Tells bundler where to find the `Gemfile`
-            app_dir.join("Gemfile"),
+            "BUNDLE_GEMFILE", // Tells bundler where to find the `Gemfile` (or `gems.rb`)
+            gemfile,
         )
         .chainable_insert(
             Scope::All,
@@ -295,6 +572,28 @@ fn layer_env(layer_path: &Path, app_dir: &Path, without_default: &BundleWithout)
             "BUNDLE_DEPLOYMENT", // Requires the `Gemfile.lock` to be in sync with the current `Gemfile`.
             "1",
         );
+    let layer_env = if without == launch_without {
+        layer_env.chainable_insert(
+            Scope::All,
+            ModificationBehavior::Default,
+            "BUNDLE_WITHOUT", // Do not install `development` or `test` groups via bundle install. Additional groups can be specified via user config.
+            without.as_str(),
+        )
+    } else {
+        layer_env
+            .chainable_insert(
+                Scope::Build,
+                ModificationBehavior::Default,
+                "BUNDLE_WITHOUT",
+                without.as_str(),
+            )
+            .chainable_insert(
+                Scope::Launch,
+                ModificationBehavior::Default,
+                "BUNDLE_WITHOUT", // Build-only groups such as `assets` are not loaded at launch
+                launch_without.as_str(),
+            )
+    };
     // CAREFUL: Changes to these ^^^^^^^ environment variables
     //
     // Not every run is guaranteed to trigger a `bundle_install`
@@ -305,8 +604,10 @@ fn layer_env(layer_path: &Path, app_dir: &Path, without_default: &BundleWithout)
 
 /// Displays the `bundle install` command with `BUNDLE_` environment variables
 /// that we use to configure bundler.
-fn display_name(cmd: &mut Command, env: &Env) -> String {
-    fun_run::display_with_env_keys(
+///
+/// Gem source credentials are shown by name with their values redacted.
+fn display_name(cmd: &mut Command, env: &Env, credentials: &GemCredentials) -> String {
+    credentials.redact(&fun_run::display_with_env_keys(
         cmd,
         env,
         [
@@ -314,10 +615,14 @@ fn display_name(cmd: &mut Command, env: &Env) -> String {
             "BUNDLE_CLEAN",
             "BUNDLE_DEPLOYMENT",
             "BUNDLE_GEMFILE",
+            "BUNDLE_JOBS",
             "BUNDLE_PATH",
+            "BUNDLE_RETRY",
             "BUNDLE_WITHOUT",
-        ],
-    )
+        ]
+        .into_iter()
+        .chain(credentials.keys()),
+    ))
 }
 
 #[derive(Deserialize, Serialize, Debug, Clone, Eq, PartialEq, Default)]
@@ -350,10 +655,14 @@ mod test {
 
         let old = Metadata {
             ruby_version: ResolvedRubyVersion("3.5.3".to_string()),
+            ruby_engine: RubyEngine::Mri,
+            ruby_engine_version: "3.5.3".to_string(),
             distro_name: "ubuntu".to_string(),
             distro_version: "20.04".to_string(),
             cpu_architecture: "amd64".to_string(),
             force_bundle_install_key: FORCE_BUNDLE_INSTALL_CACHE_KEY.to_string(),
+            without: BundleWithout::new("development:test"),
+            launch_without: BundleWithout::new("development:test"),
             digest: MetadataDigest::new_env_files(
                 &context.platform,
                 &[&context.app_path.join("Gemfile")],
@@ -364,11 +673,8 @@ mod test {
 
         let diff = Metadata {
             ruby_version: ResolvedRubyVersion("3.5.5".to_string()),
-            distro_name: old.distro_name.clone(),
-            distro_version: old.distro_version.clone(),
-            cpu_architecture: old.cpu_architecture.clone(),
-            force_bundle_install_key: old.force_bundle_install_key.clone(),
-            digest: old.digest.clone(),
+            ruby_engine_version: "3.5.5".to_string(),
+            ..old.clone()
         }
         .diff(&old);
         assert_eq!(
@@ -376,12 +682,27 @@ mod test {
             vec!["Ruby version (`3.5.3` to `3.5.5`)".to_string()]
         );
 
+        let diff = Metadata {
+            ruby_engine: RubyEngine::Jruby,
+            ruby_engine_version: "10.0.0.0".to_string(),
+            ..old.clone()
+        }
+        .diff(&old);
+        assert_eq!(
+            diff.iter().map(strip_ansi).collect::<Vec<String>>(),
+            vec!["Ruby version (`3.5.3` to `3.5.3 (jruby 10.0.0.0)`)".to_string()]
+        );
+
         let diff = Metadata {
             ruby_version: old.ruby_version.clone(),
+            ruby_engine: old.ruby_engine,
+            ruby_engine_version: old.ruby_engine_version.clone(),
             distro_name: "alpine".to_string(),
             distro_version: "3.20.0".to_string(),
             cpu_architecture: old.cpu_architecture.clone(),
             force_bundle_install_key: old.force_bundle_install_key.clone(),
+            without: old.without.clone(),
+            launch_without: old.launch_without.clone(),
             digest: old.digest.clone(),
         }
         .diff(&old);
@@ -393,10 +714,14 @@ mod test {
 
         let diff = Metadata {
             ruby_version: old.ruby_version.clone(),
+            ruby_engine: old.ruby_engine,
+            ruby_engine_version: old.ruby_engine_version.clone(),
             distro_name: old.distro_name.clone(),
             distro_version: old.distro_version.clone(),
             cpu_architecture: "arm64".to_string(),
             force_bundle_install_key: old.force_bundle_install_key.clone(),
+            without: old.without.clone(),
+            launch_without: old.launch_without.clone(),
             digest: old.digest.clone(),
         }
         .diff(&old);
@@ -404,6 +729,20 @@ mod test {
             diff.iter().map(strip_ansi).collect::<Vec<String>>(),
             vec!["CPU architecture (`amd64` to `arm64`)".to_string()]
         );
+
+        // Changing gem groups re-runs `bundle install` without clearing the cache
+        let now = Metadata {
+            launch_without: BundleWithout::new("development:test:assets"),
+            ..old.clone()
+        };
+        assert_eq!(now.diff(&old), Vec::<String>::new());
+        match install_state(&old, &now) {
+            InstallState::Run(reason) => assert_eq!(
+                "Gem groups changed (`BUNDLE_WITHOUT=development:test` to `BUNDLE_WITHOUT=development:test, at launch development:test:assets`)",
+                strip_ansi(reason)
+            ),
+            InstallState::Skip(_) => panic!("expected bundle install to run"),
+        }
     }
 
     #[cfg(test)]
@@ -429,6 +768,46 @@ mod test {
         }
     }
 
+    #[test]
+    fn test_bundle_without_from_config() {
+        let mut env = libcnb::Env::new();
+        let mut config = ProjectConfig::default();
+        assert_eq!(
+            (BundleWithout::new("development:test"), "default"),
+            BundleWithout::from_config(&env, &config)
+        );
+
+        config.bundle_without = Some(vec![String::from("test"), String::from(" ci ")]);
+        assert_eq!(
+            (BundleWithout::new("test:ci"), "project.toml"),
+            BundleWithout::from_config(&env, &config)
+        );
+
+        config.bundle_without = Some(Vec::new());
+        assert_eq!(
+            (BundleWithout::new(""), "project.toml"),
+            BundleWithout::from_config(&env, &config)
+        );
+
+        env.insert("BUNDLE_WITHOUT", "development test");
+        assert_eq!(
+            (BundleWithout::new("development test"), "BUNDLE_WITHOUT"),
+            BundleWithout::from_config(&env, &config)
+        );
+    }
+
+    #[test]
+    fn test_bundle_without_with() {
+        let without = BundleWithout::new("development test");
+        assert!(without.contains("test"));
+        assert!(!without.contains("assets"));
+        assert_eq!(
+            BundleWithout::new("development test:assets"),
+            without.with(&[String::from("assets"), String::from("test")])
+        );
+        assert_eq!(without, without.with(&[]));
+    }
+
     /// If this test fails due to user change you may need
     /// to rev a cache key to force 'bundle install'
     /// to re-run otherwise it won't be picked up by
@@ -437,7 +816,8 @@ mod test {
     fn layer_env_change_keep_guard() {
         let layer_env = layer_env(
             &PathBuf::from("layer_path"),
-            &PathBuf::from("app_path"),
+            &PathBuf::from("app_path").join("Gemfile"),
+            &BundleWithout(String::from("development:test")),
             &BundleWithout(String::from("development:test")),
         );
 
@@ -456,6 +836,39 @@ GEM_PATH=layer_path
         assert_eq!(expected.trim(), actual.trim());
     }
 
+    #[test]
+    fn layer_env_build_only_groups() {
+        let layer_env = layer_env(
+            &PathBuf::from("layer_path"),
+            &PathBuf::from("app_path").join("Gemfile"),
+            &BundleWithout(String::from("development:test")),
+            &BundleWithout(String::from("development:test:assets")),
+        );
+
+        let build = layer_env.apply(Scope::Build, &Env::new());
+        let launch = layer_env.apply(Scope::Launch, &Env::new());
+        assert_eq!(
+            Some("development:test"),
+            build.get("BUNDLE_WITHOUT").and_then(|value| value.to_str())
+        );
+        assert_eq!(
+            Some("development:test:assets"),
+            launch
+                .get("BUNDLE_WITHOUT")
+                .and_then(|value| value.to_str())
+        );
+
+        let mut user_env = Env::new();
+        user_env.insert("BUNDLE_WITHOUT", "test");
+        assert_eq!(
+            Some("test"),
+            layer_env
+                .apply(Scope::Launch, &user_env)
+                .get("BUNDLE_WITHOUT")
+                .and_then(|value| value.to_str())
+        );
+    }
+
     /// Guards the current metadata deserialization
     /// If this fails you need to implement a migration from the last format
     /// to the current format.
@@ -480,7 +893,11 @@ GEM_PATH=layer_path
             distro_version: target_id.distro_version,
             cpu_architecture: target_id.cpu_architecture,
             ruby_version: ResolvedRubyVersion(String::from("3.1.3")),
+            ruby_engine: RubyEngine::Mri,
+            ruby_engine_version: String::from("3.1.3"),
             force_bundle_install_key: String::from("v1"),
+            without: BundleWithout::new("development:test"),
+            launch_without: BundleWithout::new("development:test:assets"),
             digest: MetadataDigest::new_env_files(
                 &context.platform,
                 &[&context.app_path.join("Gemfile")],
@@ -496,7 +913,11 @@ distro_name = "ubuntu"
 distro_version = "22.04"
 cpu_architecture = "amd64"
 ruby_version = "3.1.3"
+ruby_engine = "ruby"
+ruby_engine_version = "3.1.3"
 force_bundle_install_key = "v1"
+without = "development:test"
+launch_without = "development:test:assets"
 
 [digest]
 platform_env = "c571543beaded525b7ee46ceb0b42c0fb7b9f6bfc3a211b3bbcfe6956b69ace3"
@@ -574,4 +995,92 @@ platform_env = "c571543beaded525b7ee46ceb0b42c0fb7b9f6bfc3a211b3bbcfe6956b69ace3
         };
         assert_eq!(expected, deserialized);
     }
+
+    #[test]
+    fn metadata_migrate_v2_to_v3() {
+        let tmpdir = tempfile::tempdir().unwrap();
+        let app_path = tmpdir.path().to_path_buf();
+        let gemfile = app_path.join("Gemfile");
+        let context = FakeContext {
+            platform: FakePlatform { env: Env::new() },
+            app_path,
+        };
+        std::fs::write(&gemfile, "iamagemfile").unwrap();
+
+        let metadata = MetadataV2 {
+            distro_name: String::from("ubuntu"),
+            distro_version: String::from("22.04"),
+            cpu_architecture: String::from("amd64"),
+            ruby_version: ResolvedRubyVersion(String::from("2.6.8-jruby-9.3.6.0")),
+            force_bundle_install_key: String::from("v1"),
+            digest: MetadataDigest::new_env_files(
+                &context.platform,
+                &[&context.app_path.join("Gemfile")],
+            )
+            .unwrap(),
+        };
+
+        let deserialized: MetadataV3 =
+            MetadataV3::try_from_str_migrations(&toml::to_string(&metadata).unwrap())
+                .unwrap()
+                .unwrap();
+
+        let expected = MetadataV3 {
+            distro_name: metadata.distro_name,
+            distro_version: metadata.distro_version,
+            cpu_architecture: metadata.cpu_architecture,
+            ruby_version: ResolvedRubyVersion(String::from("2.6.8")),
+            ruby_engine: RubyEngine::Jruby,
+            ruby_engine_version: String::from("9.3.6.0"),
+            force_bundle_install_key: metadata.force_bundle_install_key,
+            digest: metadata.digest,
+        };
+        assert_eq!(expected, deserialized);
+    }
+
+    #[test]
+    fn metadata_migrate_v3_to_v4() {
+        let tmpdir = tempfile::tempdir().unwrap();
+        let app_path = tmpdir.path().to_path_buf();
+        let gemfile = app_path.join("Gemfile");
+        let context = FakeContext {
+            platform: FakePlatform { env: Env::new() },
+            app_path,
+        };
+        std::fs::write(&gemfile, "iamagemfile").unwrap();
+
+        let metadata = MetadataV3 {
+            distro_name: String::from("ubuntu"),
+            distro_version: String::from("22.04"),
+            cpu_architecture: String::from("amd64"),
+            ruby_version: ResolvedRubyVersion(String::from("3.3.6")),
+            ruby_engine: RubyEngine::Mri,
+            ruby_engine_version: String::from("3.3.6"),
+            force_bundle_install_key: String::from("v1"),
+            digest: MetadataDigest::new_env_files(
+                &context.platform,
+                &[&context.app_path.join("Gemfile")],
+            )
+            .unwrap(),
+        };
+
+        let deserialized: MetadataV4 =
+            MetadataV4::try_from_str_migrations(&toml::to_string(&metadata).unwrap())
+                .unwrap()
+                .unwrap();
+
+        let expected = MetadataV4 {
+            distro_name: metadata.distro_name,
+            distro_version: metadata.distro_version,
+            cpu_architecture: metadata.cpu_architecture,
+            ruby_version: metadata.ruby_version,
+            ruby_engine: metadata.ruby_engine,
+            ruby_engine_version: metadata.ruby_engine_version,
+            force_bundle_install_key: metadata.force_bundle_install_key,
+            without: BundleWithout::new("development:test"),
+            launch_without: BundleWithout::new("development:test"),
+            digest: metadata.digest,
+        };
+        assert_eq!(expected, deserialized);
+    }
 }
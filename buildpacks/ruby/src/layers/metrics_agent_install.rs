@@ -1,3 +1,5 @@
+use crate::archive::{self, UnpackError};
+use crate::download::{download, DownloadError};
 use crate::{RubyBuildpack, RubyBuildpackError};
 use bullet_stream::state::SubBullet;
 use bullet_stream::{style, Print};
@@ -12,7 +14,6 @@ use serde::{Deserialize, Serialize};
 use std::io::Stdout;
 use std::os::unix::fs::PermissionsExt;
 use std::path::{Path, PathBuf};
-use tar::Archive;
 use tempfile::NamedTempFile;
 
 /// Agentmon URL
@@ -43,11 +44,10 @@ pub(crate) enum MetricsAgentInstallError {
     CouldNotOpenFile(std::io::Error),
 
     #[error("Could not untar: {0}")]
-    CouldNotUnpack(std::io::Error),
+    CouldNotUnpack(UnpackError),
 
-    // Boxed to prevent `large_enum_variant` errors since `ureq::Error` is massive.
-    #[error("Download error: {0}")]
-    RequestError(Box<ureq::Error>),
+    #[error(transparent)]
+    DownloadError(DownloadError),
 
     #[error("Could not create file: {0}")]
     CouldNotCreateDestinationFile(std::io::Error),
@@ -181,7 +181,7 @@ fn download_untar(
     let agentmon_tgz =
         NamedTempFile::new().map_err(MetricsAgentInstallError::CouldNotCreateDestinationFile)?;
 
-    download(url, agentmon_tgz.path())?;
+    download(url.as_ref(), agentmon_tgz.path()).map_err(MetricsAgentInstallError::DownloadError)?;
 
     sha256(agentmon_tgz.path())
         .map_err(MetricsAgentInstallError::CouldNotOpenFile)
@@ -205,8 +205,7 @@ fn untar(
     let file =
         fs_err::File::open(path.as_ref()).map_err(MetricsAgentInstallError::CouldNotOpenFile)?;
 
-    Archive::new(GzDecoder::new(file))
-        .unpack(destination.as_ref())
+    archive::unpack(GzDecoder::new(file), destination.as_ref(), 0)
         .map_err(MetricsAgentInstallError::CouldNotUnpack)
 }
 
@@ -224,24 +223,6 @@ fn chmod_plus_x(path: &Path) -> Result<(), std::io::Error> {
     fs_err::set_permissions(path, perms)
 }
 
-fn download(
-    uri: impl AsRef<str>,
-    destination: impl AsRef<Path>,
-) -> Result<(), MetricsAgentInstallError> {
-    let mut response_reader = ureq::get(uri.as_ref())
-        .call()
-        .map_err(|err| MetricsAgentInstallError::RequestError(Box::new(err)))?
-        .into_reader();
-
-    let mut destination_file = fs_err::File::create(destination.as_ref())
-        .map_err(MetricsAgentInstallError::CouldNotCreateDestinationFile)?;
-
-    std::io::copy(&mut response_reader, &mut destination_file)
-        .map_err(MetricsAgentInstallError::CouldNotWriteDestinationFile)?;
-
-    Ok(())
-}
-
 #[cfg(test)]
 mod tests {
     use super::*;
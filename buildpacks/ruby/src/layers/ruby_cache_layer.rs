@@ -0,0 +1,286 @@
+//! # Cache of previously installed Ruby versions
+//!
+//! ## Layer dir
+//!
+//! A cache-only layer, it is not available at build or launch time. When the Ruby version
+//! changes, the previous Ruby in the `ruby` layer (see `ruby_install_layer`) is copied to
+//! `<layer-dir>/<entry>/` before the layer is cleared. A Ruby that is already cached is copied
+//! back instead of downloading it again. The Ruby in use is only stored once it is replaced, so
+//! a build that never changes versions does not keep a second copy.
+//!
+//! The cache is an optimization, errors while copying are returned as `RubyCacheError` for the
+//! caller to print as a warning, the Ruby is then downloaded or not stored.
+//!
+//! ## Cache invalidation
+//!
+//! Entries are never invalidated, they are identified by the full Ruby and target metadata. At
+//! most `MAX_CACHED_RUBIES` are kept, the least recently used entry is removed first.
+//!
+use crate::layers::ruby_install_layer;
+use crate::{RubyBuildpack, RubyBuildpackError};
+use libcnb::build::BuildContext;
+use libcnb::data::layer_name;
+use libcnb::layer::{
+    CachedLayerDefinition, InvalidMetadataAction, LayerRef, LayerState, RestoredLayerAction,
+};
+use magic_migrate::{try_migrate_deserializer_chain, TryMigrate};
+use serde::{Deserialize, Deserializer, Serialize};
+use std::convert::Infallible;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Number of previously used Ruby versions kept in the cache
+pub(crate) const MAX_CACHED_RUBIES: usize = 3;
+
+#[derive(Deserialize, Serialize, Debug, Clone, Default, PartialEq, Eq)]
+pub(crate) struct MetadataV1 {
+    /// Cached Rubies, most recently used first
+    pub(crate) rubies: Vec<ruby_install_layer::Metadata>,
+}
+pub(crate) type Metadata = MetadataV1;
+
+try_migrate_deserializer_chain!(
+    chain: [MetadataV1],
+    error: MetadataError,
+    deserializer: toml::Deserializer::new,
+);
+
+#[derive(Debug, thiserror::Error)]
+pub(crate) enum MetadataError {
+    // Update if migrating between a metadata version can error
+}
+
+pub(crate) struct RubyCache {
+    layer_ref: LayerRef<RubyBuildpack, (), Metadata>,
+    metadata: Metadata,
+}
+
+impl RubyCache {
+    pub(crate) fn open(
+        context: &BuildContext<RubyBuildpack>,
+    ) -> libcnb::Result<Self, RubyBuildpackError> {
+        let layer_ref = context.cached_layer(
+            layer_name!("ruby_cache"),
+            CachedLayerDefinition {
+                build: false,
+                launch: false,
+                invalid_metadata_action: &|_| InvalidMetadataAction::DeleteLayer,
+                restored_layer_action: &|old: &Metadata, _| {
+                    (RestoredLayerAction::KeepLayer, old.clone())
+                },
+            },
+        )?;
+        let metadata = match &layer_ref.state {
+            LayerState::Restored { cause } => cause.clone(),
+            LayerState::Empty { .. } => Metadata::default(),
+        };
+        Ok(Self {
+            layer_ref,
+            metadata,
+        })
+    }
+
+    pub(crate) fn contains(&self, ruby: &ruby_install_layer::Metadata) -> bool {
+        self.metadata.rubies.contains(ruby)
+    }
+
+    /// Copies a cached Ruby into `destination`, returns `false` when it is not cached
+    ///
+    /// The entry becomes the most recently used.
+    pub(crate) fn restore(
+        &mut self,
+        ruby: &ruby_install_layer::Metadata,
+        destination: &Path,
+    ) -> Result<bool, RubyCacheError> {
+        if !self.contains(ruby) {
+            return Ok(false);
+        }
+        copy_dir(&self.entry_path(ruby), destination).map_err(RubyCacheError::CouldNotRestore)?;
+        self.touch(ruby);
+        Ok(true)
+    }
+
+    /// Copies the Ruby in `source` into the cache, or marks it as most recently used when it
+    /// is already cached
+    ///
+    /// Removes the least recently used entries when the cache is full.
+    pub(crate) fn insert(
+        &mut self,
+        ruby: &ruby_install_layer::Metadata,
+        source: &Path,
+    ) -> Result<(), RubyCacheError> {
+        if !self.contains(ruby) {
+            let entry = self.entry_path(ruby);
+            remove_path(&entry).map_err(RubyCacheError::CouldNotStore)?;
+            if let Err(error) = copy_dir(source, &entry) {
+                // A partial copy must not be restored later
+                let _ = remove_path(&entry);
+                return Err(RubyCacheError::CouldNotStore(error));
+            }
+        }
+        self.touch(ruby);
+
+        while self.metadata.rubies.len() > MAX_CACHED_RUBIES {
+            if let Some(oldest) = self.metadata.rubies.pop() {
+                remove_path(&self.entry_path(&oldest)).map_err(RubyCacheError::CouldNotStore)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Removes a Ruby from the cache i.e. when it failed verification
+    pub(crate) fn remove(
+        &mut self,
+        ruby: &ruby_install_layer::Metadata,
+    ) -> Result<(), RubyCacheError> {
+        self.metadata.rubies.retain(|cached| cached != ruby);
+        remove_path(&self.entry_path(ruby)).map_err(RubyCacheError::CouldNotStore)
+    }
+
+    /// Writes the list of cached Rubies for the next build
+    pub(crate) fn save(&self) -> libcnb::Result<(), RubyBuildpackError> {
+        self.layer_ref.write_metadata(&self.metadata)
+    }
+
+    fn touch(&mut self, ruby: &ruby_install_layer::Metadata) {
+        self.metadata.rubies.retain(|cached| cached != ruby);
+        self.metadata.rubies.insert(0, ruby.clone());
+    }
+
+    fn entry_path(&self, ruby: &ruby_install_layer::Metadata) -> PathBuf {
+        self.layer_ref.path().join(entry_name(ruby))
+    }
+}
+
+#[derive(thiserror::Error, Debug)]
+pub(crate) enum RubyCacheError {
+    #[error("Could not restore Ruby from the cache: {0}")]
+    CouldNotRestore(io::Error),
+
+    #[error("Could not store Ruby in the cache: {0}")]
+    CouldNotStore(io::Error),
+}
+
+/// Custom Ruby builds are identified by their checksum, so they do not replace the inventory
+/// build of the same version
+fn entry_name(ruby: &ruby_install_layer::Metadata) -> String {
+    let name = format!(
+        "{}-{}-{}-{}-{}-{}",
+        ruby.distro_name,
+        ruby.distro_version,
+        ruby.cpu_architecture,
+        ruby.ruby_engine,
+        ruby.ruby_engine_version,
+        ruby.ruby_version
+    );
+    match &ruby.custom_ruby {
+        Some(custom) => format!(
+            "{name}-custom-{}",
+            custom.sha256.chars().take(12).collect::<String>()
+        ),
+        None => name,
+    }
+}
+
+fn remove_path(path: &Path) -> Result<(), io::Error> {
+    match fs_err::symlink_metadata(path) {
+        Ok(metadata) if metadata.is_dir() => fs_err::remove_dir_all(path),
+        Ok(_) => fs_err::remove_file(path),
+        Err(error) if error.kind() == io::ErrorKind::NotFound => Ok(()),
+        Err(error) => Err(error),
+    }
+}
+
+/// Recursively copies the contents of `source` into `destination`, preserving symlinks and
+/// file permissions
+fn copy_dir(source: &Path, destination: &Path) -> Result<(), io::Error> {
+    fs_err::create_dir_all(destination)?;
+    for entry in fs_err::read_dir(source)? {
+        let entry = entry?;
+        let from = entry.path();
+        let to = destination.join(entry.file_name());
+        let file_type = entry.file_type()?;
+        if file_type.is_symlink() {
+            fs_err::os::unix::fs::symlink(fs_err::read_link(&from)?, &to)?;
+        } else if file_type.is_dir() {
+            copy_dir(&from, &to)?;
+        } else {
+            fs_err::copy(&from, &to)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::layers::shared::temp_build_context;
+    use commons::gemfile_lock::{ResolvedRubyVersion, RubyEngine};
+
+    fn ruby(version: &str) -> ruby_install_layer::Metadata {
+        ruby_install_layer::Metadata {
+            distro_name: String::from("ubuntu"),
+            distro_version: String::from("24.04"),
+            cpu_architecture: String::from("amd64"),
+            ruby_version: ResolvedRubyVersion(version.to_string()),
+            ruby_engine: RubyEngine::Mri,
+            ruby_engine_version: version.to_string(),
+            custom_ruby: None,
+        }
+    }
+
+    fn install(dir: &Path, version: &str) {
+        fs_err::create_dir_all(dir.join("bin")).unwrap();
+        fs_err::write(dir.join("bin").join("ruby"), version).unwrap();
+        fs_err::os::unix::fs::symlink("ruby", dir.join("bin").join("irb")).unwrap();
+    }
+
+    #[test]
+    fn test_restore_and_prune() {
+        let temp = tempfile::tempdir().unwrap();
+        let context = temp_build_context::<RubyBuildpack>(temp.path());
+        let versions = ["3.1.6", "3.2.6", "3.3.6", "3.4.1"];
+
+        let mut cache = RubyCache::open(&context).unwrap();
+        for version in versions {
+            let installed = temp.path().join(version);
+            install(&installed, version);
+            cache.insert(&ruby(version), &installed).unwrap();
+        }
+        assert_eq!(MAX_CACHED_RUBIES, cache.metadata.rubies.len());
+        assert!(!cache.contains(&ruby("3.1.6")));
+        assert!(!cache.entry_path(&ruby("3.1.6")).exists());
+
+        // Metadata is kept across builds once saved
+        cache.save().unwrap();
+        let mut cache = RubyCache::open(&context).unwrap();
+        assert_eq!(ruby("3.4.1"), cache.metadata.rubies[0]);
+
+        let layer = temp.path().join("layer");
+        assert!(!cache.restore(&ruby("3.1.6"), &layer).unwrap());
+        assert!(cache.restore(&ruby("3.2.6"), &layer).unwrap());
+        assert_eq!(
+            "3.2.6",
+            fs_err::read_to_string(layer.join("bin").join("irb")).unwrap()
+        );
+        assert_eq!(ruby("3.2.6"), cache.metadata.rubies[0]);
+
+        // Restoring made 3.3.6 the least recently used
+        let installed = temp.path().join("3.0.7");
+        install(&installed, "3.0.7");
+        cache.insert(&ruby("3.0.7"), &installed).unwrap();
+        assert!(!cache.contains(&ruby("3.3.6")));
+        assert!(cache.contains(&ruby("3.2.6")));
+
+        cache.remove(&ruby("3.2.6")).unwrap();
+        assert!(!cache.contains(&ruby("3.2.6")));
+        assert!(!cache.entry_path(&ruby("3.2.6")).exists());
+
+        // A failed copy is not kept
+        assert!(cache
+            .insert(&ruby("2.7.8"), &temp.path().join("missing"))
+            .is_err());
+        assert!(!cache.contains(&ruby("2.7.8")));
+        assert!(!cache.entry_path(&ruby("2.7.8")).exists());
+    }
+}
@@ -2,45 +2,111 @@
 //!
 //! ## Layer dir
 //!
-//! The compiled Ruby tgz file is downloaded to a temporary directory and exported to `<layer-dir>`.
+//! The compiled Ruby tgz file listed in the inventory (see `ruby_inventory`) is streamed
+//! from the binary mirror (see `BinaryMirror`) through the decompressor into `<layer-dir>`
+//! without being written to disk. Its sha256 checksum is computed while streaming and verified
+//! once the download completes, the unpacked files are removed when it does not match.
 //! The tgz already contains a `bin/` directory with a `ruby` executable file.
 //!
+//! A custom Ruby build (see `CustomRuby`) replaces the inventory and mirror, it is downloaded
+//! from the configured URL and verified against the configured checksum.
+//!
 //! This layer relies on the CNB lifecycle to add `<layer-dir>/bin` to the PATH.
 //!
+//! ## Verification
+//!
+//! After installing or restoring, `ruby -v` is run and the `openssl`, `psych`, and `zlib`
+//! libraries are loaded, and the reported `RUBY_VERSION` must match the metadata. A restored
+//! layer that fails is wiped and reinstalled, a fresh install that fails errors.
+//!
 //! ## Cache invalidation
 //!
-//! When the Ruby version changes, invalidate and re-run.
+//! When the Ruby version, engine, or custom Ruby URL or checksum changes, invalidate and re-run. The
+//! previous Ruby is copied to a separate cache (see `ruby_cache_layer`) before the layer is cleared,
+//! a previously installed Ruby is copied from there instead of being downloaded again.
 //!
-use crate::layers::shared::{cached_layer_write_metadata, MetadataDiff};
+use crate::layers::ruby_cache_layer::{RubyCache, RubyCacheError};
+use crate::layers::shared::{invalid_metadata_action, restored_layer_action, MetadataDiff};
 use crate::{
-    target_id::{TargetId, TargetIdError},
+    archive::{self, UnpackError},
+    download::{human_bytes, Download, DownloadError, Sha256Reader},
+    project_config::{ProjectConfig, PROJECT_TOML},
+    ruby_inventory::{Inventory, Suggestions},
+    target_id::{TargetId, TargetIdError, UrlLayout},
     RubyBuildpack, RubyBuildpackError,
 };
 use bullet_stream::state::SubBullet;
 use bullet_stream::{style, Print};
-use commons::gemfile_lock::ResolvedRubyVersion;
+use commons::gemfile_lock::{ResolvedRuby, ResolvedRubyVersion, RubyEngine};
 use flate2::read::GzDecoder;
+use fun_run::{CmdError, CommandWithName};
 use libcnb::data::layer_name;
-use libcnb::layer::{EmptyLayerCause, LayerState};
+use libcnb::layer::{CachedLayerDefinition, EmptyLayerCause, LayerState, RestoredLayerAction};
 use libcnb::layer_env::LayerEnv;
+use libcnb::Env;
 use magic_migrate::{try_migrate_deserializer_chain, TryMigrate};
 use serde::{Deserialize, Deserializer, Serialize};
+use std::cell::RefCell;
 use std::convert::Infallible;
-use std::io::{self, Stdout};
+use std::io::{self, Read, Stdout};
 use std::path::Path;
-use tar::Archive;
-use tempfile::NamedTempFile;
+use std::process::Command;
 use url::Url;
 
 pub(crate) fn handle(
     context: &libcnb::build::BuildContext<RubyBuildpack>,
+    env: &Env,
     mut bullet: Print<SubBullet<Stdout>>,
     metadata: &Metadata,
+    inventory: &Inventory,
+    mirror: &BinaryMirror,
 ) -> libcnb::Result<(Print<SubBullet<Stdout>>, LayerEnv), RubyBuildpackError> {
-    let layer_ref = cached_layer_write_metadata(layer_name!("ruby"), context, metadata)?;
+    let cache = RefCell::new(RubyCache::open(context)?);
+    let stored = RefCell::new(Ok(()));
+    let layer_ref = context.cached_layer(
+        layer_name!("ruby"),
+        CachedLayerDefinition {
+            build: true,
+            launch: true,
+            invalid_metadata_action: &invalid_metadata_action,
+            restored_layer_action: &|old: &Metadata, path: &Path| {
+                let action = restored_layer_action(old, metadata);
+                // Keep the previous Ruby before the layer is cleared for the new one
+                if matches!(action.0, RestoredLayerAction::DeleteLayer) {
+                    *stored.borrow_mut() = cache.borrow_mut().insert(old, path);
+                }
+                action
+            },
+        },
+    )?;
+    layer_ref.write_metadata(metadata)?;
+    let mut cache = cache.into_inner();
+    if let Err(error) = stored.into_inner() {
+        bullet = cache_warning(bullet, &error);
+    }
+    let layer_path = layer_ref.path();
     match &layer_ref.state {
         LayerState::Restored { cause } => {
             bullet = bullet.sub_bullet(cause);
+            match verify_ruby(env, metadata, &layer_path) {
+                Ok(version) => {
+                    bullet = bullet.sub_bullet(verified(&version));
+                }
+                Err(error) => {
+                    bullet = bullet.sub_bullet(format!(
+                        "Reinstalling, cached Ruby failed verification: {error}"
+                    ));
+                    if let Err(error) = cache.remove(metadata) {
+                        bullet = cache_warning(bullet, &error);
+                    }
+                    clear_dir(&layer_path)
+                        .map_err(RubyInstallError::CouldNotUnpack)
+                        .map_err(RubyBuildpackError::RubyInstallError)?;
+                    bullet = install_ruby(bullet, metadata, inventory, mirror, &layer_path)?;
+                    bullet =
+                        bullet.sub_bullet(verified(&verify_installed(env, metadata, &layer_path)?));
+                }
+            }
         }
         LayerState::Empty { cause } => {
             match cause {
@@ -50,29 +116,223 @@ pub(crate) fn handle(
                     bullet = bullet.sub_bullet(cause);
                 }
             }
-            let timer = bullet.start_timer("Installing");
-            install_ruby(metadata, &layer_ref.path())?;
-            bullet = timer.done();
+            let cached = match cache.restore(metadata, &layer_path) {
+                Ok(true) => {
+                    bullet = bullet.sub_bullet(format!(
+                        "Using cached Ruby {} from previous build",
+                        style::value(metadata.ruby().to_string())
+                    ));
+                    match verify_ruby(env, metadata, &layer_path) {
+                        Ok(version) => Some(version),
+                        Err(error) => {
+                            bullet = bullet.sub_bullet(format!(
+                                "Reinstalling, cached Ruby failed verification: {error}"
+                            ));
+                            if let Err(error) = cache.remove(metadata) {
+                                bullet = cache_warning(bullet, &error);
+                            }
+                            None
+                        }
+                    }
+                }
+                Ok(false) => None,
+                Err(error) => {
+                    bullet = cache_warning(bullet, &error);
+                    if let Err(error) = cache.remove(metadata) {
+                        bullet = cache_warning(bullet, &error);
+                    }
+                    None
+                }
+            };
+            let version = if let Some(version) = cached {
+                version
+            } else {
+                clear_dir(&layer_path)
+                    .map_err(RubyInstallError::CouldNotUnpack)
+                    .map_err(RubyBuildpackError::RubyInstallError)?;
+                bullet = install_ruby(bullet, metadata, inventory, mirror, &layer_path)?;
+                verify_installed(env, metadata, &layer_path)?
+            };
+            bullet = bullet.sub_bullet(verified(&version));
         }
     }
+    cache.save()?;
     Ok((bullet, layer_ref.read_env()?))
 }
 
-fn install_ruby(metadata: &Metadata, layer_path: &Path) -> Result<(), RubyBuildpackError> {
-    let tmp_ruby_tgz = NamedTempFile::new()
-        .map_err(RubyInstallError::CouldNotCreateDestinationFile)
-        .map_err(RubyBuildpackError::RubyInstallError)?;
+/// The Ruby cache is an optimization, the build continues without it
+fn cache_warning(
+    bullet: Print<SubBullet<Stdout>>,
+    error: &RubyCacheError,
+) -> Print<SubBullet<Stdout>> {
+    bullet.warning(format!(
+        "{error}\n\nContinuing without the Ruby cache, this does not affect the build."
+    ))
+}
 
-    let url = download_url(&metadata.target_id(), &metadata.ruby_version)
-        .map_err(RubyBuildpackError::RubyInstallError)?;
+/// Libraries that are part of the standard library but link against system packages, a Ruby
+/// built for a different distribution fails to load them
+const VERIFY_SCRIPT: &str =
+    r#"require "openssl"; require "psych"; require "zlib"; puts RUBY_VERSION"#;
 
-    download(url.as_ref(), tmp_ruby_tgz.path()).map_err(RubyBuildpackError::RubyInstallError)?;
+/// Runs the Ruby in the layer to make sure it works
+///
+/// Prints the version with `ruby -v`, loads the native extensions of the standard library that
+/// link against system libraries, and confirms that `RUBY_VERSION` matches the metadata.
+/// Returns the output of `ruby -v`.
+fn verify_ruby(env: &Env, metadata: &Metadata, layer_path: &Path) -> Result<String, VerifyError> {
+    let mut cmd = Command::new(layer_path.join("bin").join("ruby"));
+    cmd.args(["-v", "-e", VERIFY_SCRIPT]).env_clear().envs(env);
+    let output = cmd
+        .named(format!("ruby -v -e '{VERIFY_SCRIPT}'"))
+        .named_output()
+        .map_err(|error| VerifyError::Command(Box::new(error)))?;
 
-    untar(tmp_ruby_tgz.path(), layer_path).map_err(RubyBuildpackError::RubyInstallError)?;
+    let stdout = output.stdout_lossy();
+    let mut lines = stdout
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty());
+    let description = lines.next().unwrap_or_default().to_string();
+    let reported = lines.next_back().unwrap_or_default();
+    let expected = &metadata.ruby_version.0;
+    // Prereleases such as `3.4.0.preview2` report `RUBY_VERSION` without the prerelease
+    if reported.is_empty()
+        || !(expected == reported || expected.starts_with(&format!("{reported}.")))
+    {
+        return Err(VerifyError::VersionMismatch {
+            expected: metadata.ruby_version.clone(),
+            actual: reported.to_string(),
+        });
+    }
+    Ok(description)
+}
+
+fn verify_installed(
+    env: &Env,
+    metadata: &Metadata,
+    layer_path: &Path,
+) -> Result<String, RubyBuildpackError> {
+    verify_ruby(env, metadata, layer_path)
+        .map_err(Box::new)
+        .map_err(RubyInstallError::VerifyFailed)
+        .map_err(RubyBuildpackError::RubyInstallError)
+}
+
+fn verified(version: &str) -> String {
+    format!(
+        "Verified {} with openssl, psych, and zlib",
+        style::value(version)
+    )
+}
+
+#[derive(thiserror::Error, Debug)]
+pub(crate) enum VerifyError {
+    #[error("{0}")]
+    Command(Box<CmdError>),
 
+    #[error("Expected Ruby {expected} but `RUBY_VERSION` is `{actual}`")]
+    VersionMismatch {
+        expected: ResolvedRubyVersion,
+        actual: String,
+    },
+}
+
+/// Removes everything inside of a directory, leaving the directory in place
+fn clear_dir(dir: &Path) -> Result<(), io::Error> {
+    for entry in fs_err::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            fs_err::remove_dir_all(path)?;
+        } else {
+            fs_err::remove_file(path)?;
+        }
+    }
     Ok(())
 }
 
+fn install_ruby(
+    bullet: Print<SubBullet<Stdout>>,
+    metadata: &Metadata,
+    inventory: &Inventory,
+    mirror: &BinaryMirror,
+    layer_path: &Path,
+) -> Result<Print<SubBullet<Stdout>>, RubyBuildpackError> {
+    let mut bullet = bullet;
+    let (url, sha256) = if let Some(custom) = &metadata.custom_ruby {
+        (custom.url()?, custom.sha256.clone())
+    } else {
+        let target = metadata.target_id();
+        let ruby = metadata.ruby();
+        let sha256 = match inventory.find(&target, &ruby) {
+            Some(artifact) => artifact.sha256.clone(),
+            None => {
+                return Err(RubyBuildpackError::RubyInstallError(
+                    RubyInstallError::NotInInventory {
+                        suggestions: Box::new(inventory.suggest(&target, &ruby)),
+                        ruby,
+                        target: Box::new(target),
+                    },
+                ))
+            }
+        };
+        let url =
+            download_url(mirror, &target, &ruby).map_err(RubyBuildpackError::RubyInstallError)?;
+        (url, sha256)
+    };
+    let mut download = Download::start(url.as_str())
+        .map_err(RubyInstallError::DownloadError)
+        .map_err(RubyBuildpackError::RubyInstallError)?;
+
+    let size = download
+        .content_length()
+        .map(|length| format!(" ({})", human_bytes(u128::from(length))))
+        .unwrap_or_default();
+    let timer = bullet.start_timer(format!("Downloading {}{size}", style::url(url.as_str())));
+    let result = download_untar(&mut download, &url, &sha256, layer_path);
+    bullet = timer.done();
+    for event in download.take_events() {
+        bullet = bullet.sub_bullet(event);
+    }
+    result.map_err(RubyBuildpackError::RubyInstallError)?;
+    Ok(bullet)
+}
+
+/// Streams the download through the decompressor into the layer, hashing it along the way
+///
+/// The whole download is read, including any bytes after the end of the archive, before the
+/// checksum is compared. On a mismatch the unpacked files are deleted.
+fn download_untar(
+    download: &mut Download,
+    url: &Url,
+    expected_sha256: &str,
+    destination: &Path,
+) -> Result<(), RubyInstallError> {
+    let mut reader = Sha256Reader::new(download);
+    if let Err(error) = untar(&mut reader, destination).and_then(|()| {
+        io::copy(&mut reader, &mut io::sink())
+            .map(|_| ())
+            .map_err(RubyInstallError::CouldNotUnpack)
+    }) {
+        return Err(reader
+            .inner
+            .take_error()
+            .map_or(error, RubyInstallError::DownloadError));
+    }
+
+    let actual = reader.hex_digest();
+    if actual.eq_ignore_ascii_case(expected_sha256) {
+        Ok(())
+    } else {
+        clear_dir(destination).map_err(RubyInstallError::CouldNotUnpack)?;
+        Err(RubyInstallError::ChecksumMismatch {
+            url: url.to_string(),
+            expected: expected_sha256.to_string(),
+            actual,
+        })
+    }
+}
+
 #[derive(Deserialize, Serialize, Debug, Clone)]
 pub(crate) struct MetadataV1 {
     pub(crate) stack: String,
@@ -86,9 +346,31 @@ pub(crate) struct MetadataV2 {
     pub(crate) cpu_architecture: String,
     pub(crate) ruby_version: ResolvedRubyVersion,
 }
-pub(crate) type Metadata = MetadataV2;
 
-impl MetadataV2 {
+#[derive(Deserialize, Serialize, Debug, Clone, Eq, PartialEq)]
+pub(crate) struct MetadataV3 {
+    pub(crate) distro_name: String,
+    pub(crate) distro_version: String,
+    pub(crate) cpu_architecture: String,
+    pub(crate) ruby_version: ResolvedRubyVersion,
+    pub(crate) ruby_engine: RubyEngine,
+    pub(crate) ruby_engine_version: String,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Eq, PartialEq)]
+pub(crate) struct MetadataV4 {
+    pub(crate) distro_name: String,
+    pub(crate) distro_version: String,
+    pub(crate) cpu_architecture: String,
+    pub(crate) ruby_version: ResolvedRubyVersion,
+    pub(crate) ruby_engine: RubyEngine,
+    pub(crate) ruby_engine_version: String,
+    /// Set when Ruby is installed from a custom URL instead of the inventory
+    pub(crate) custom_ruby: Option<CustomRuby>,
+}
+pub(crate) type Metadata = MetadataV4;
+
+impl MetadataV4 {
     pub(crate) fn target_id(&self) -> TargetId {
         TargetId {
             cpu_architecture: self.cpu_architecture.clone(),
@@ -96,10 +378,18 @@ impl MetadataV2 {
             distro_version: self.distro_version.clone(),
         }
     }
+
+    pub(crate) fn ruby(&self) -> ResolvedRuby {
+        ResolvedRuby {
+            engine: self.ruby_engine,
+            engine_version: self.ruby_engine_version.clone(),
+            version: self.ruby_version.clone(),
+        }
+    }
 }
 
 try_migrate_deserializer_chain!(
-    chain: [MetadataV1, MetadataV2],
+    chain: [MetadataV1, MetadataV2, MetadataV3, MetadataV4],
     error: MetadataMigrateError,
     deserializer: toml::Deserializer::new,
 );
@@ -126,6 +416,36 @@ impl TryFrom<MetadataV1> for MetadataV2 {
     }
 }
 
+// Versions prior to V3 stored JRuby versions as `<version>-jruby-<engine_version>`
+impl From<MetadataV2> for MetadataV3 {
+    fn from(v2: MetadataV2) -> Self {
+        let ruby = ResolvedRuby::from_legacy_version(&v2.ruby_version);
+        Self {
+            distro_name: v2.distro_name,
+            distro_version: v2.distro_version,
+            cpu_architecture: v2.cpu_architecture,
+            ruby_version: ruby.version,
+            ruby_engine: ruby.engine,
+            ruby_engine_version: ruby.engine_version,
+        }
+    }
+}
+
+// Versions prior to V4 were always installed from the inventory
+impl From<MetadataV3> for MetadataV4 {
+    fn from(v3: MetadataV3) -> Self {
+        Self {
+            distro_name: v3.distro_name,
+            distro_version: v3.distro_version,
+            cpu_architecture: v3.cpu_architecture,
+            ruby_version: v3.ruby_version,
+            ruby_engine: v3.ruby_engine,
+            ruby_engine_version: v3.ruby_engine_version,
+            custom_ruby: None,
+        }
+    }
+}
+
 impl MetadataDiff for Metadata {
     fn diff(&self, old: &Self) -> Vec<String> {
         let mut differences = Vec::new();
@@ -133,15 +453,38 @@ impl MetadataDiff for Metadata {
             distro_name,
             distro_version,
             cpu_architecture,
-            ruby_version,
+            ruby_version: _,
+            ruby_engine: _,
+            ruby_engine_version: _,
+            custom_ruby,
         } = old;
-        if ruby_version != &self.ruby_version {
+        if old.ruby() != self.ruby() {
             differences.push(format!(
                 "Ruby version ({old} to {now})",
-                old = style::value(ruby_version.to_string()),
-                now = style::value(self.ruby_version.to_string())
+                old = style::value(old.ruby().to_string()),
+                now = style::value(self.ruby().to_string())
             ));
         }
+        let source = |custom: Option<&CustomRuby>| {
+            custom.map_or_else(|| String::from("inventory"), |custom| custom.url.clone())
+        };
+        match (custom_ruby, &self.custom_ruby) {
+            (Some(old), Some(now)) if old.url == now.url && old.sha256 != now.sha256 => {
+                differences.push(format!(
+                    "Ruby source checksum ({old} to {now})",
+                    old = style::value(&old.sha256),
+                    now = style::value(&now.sha256)
+                ));
+            }
+            (old, now) if old != now => {
+                differences.push(format!(
+                    "Ruby source ({old} to {now})",
+                    old = style::value(source(old.as_ref())),
+                    now = style::value(source(now.as_ref()))
+                ));
+            }
+            _ => {}
+        }
         if distro_name != &self.distro_name || distro_version != &self.distro_version {
             differences.push(format!(
                 "Distribution ({old} to {now})",
@@ -161,90 +504,232 @@ impl MetadataDiff for Metadata {
     }
 }
 
-fn download_url(
-    target: &TargetId,
-    version: impl std::fmt::Display,
-) -> Result<Url, RubyInstallError> {
-    let filename = format!("ruby-{version}.tgz");
-    let base = "https://heroku-buildpack-ruby.s3.us-east-1.amazonaws.com";
-    let mut url = Url::parse(base).map_err(RubyInstallError::UrlParseError)?;
-    {
-        let mut segments = url
-            .path_segments_mut()
-            .map_err(|()| RubyInstallError::InvalidBaseUrl(String::from(base)))?;
+/// Where Ruby binaries are downloaded from
+///
+/// Set with the `HEROKU_RUBY_BINARY_MIRROR` environment variable or `binary_mirror` in
+/// `project.toml`, the environment variable takes precedence. A mirror must follow the same
+/// layout as the default location, see `download_url`. A `file://` mirror is read from the
+/// local disk without making network requests.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct BinaryMirror {
+    pub(crate) url: Url,
+    /// Where the mirror was configured, `default` when not configured
+    pub(crate) from: &'static str,
+}
 
-        segments.push(&target.stack_name().map_err(RubyInstallError::TargetError)?);
-        if target.is_arch_aware() {
-            segments.push(&target.cpu_architecture);
+pub(crate) const MIRROR_ENV_KEY: &str = "HEROKU_RUBY_BINARY_MIRROR";
+const DEFAULT_MIRROR: &str = "https://heroku-buildpack-ruby.s3.us-east-1.amazonaws.com";
+
+impl Default for BinaryMirror {
+    fn default() -> Self {
+        Self {
+            url: Url::parse(DEFAULT_MIRROR).expect("Internal error: Bad default mirror"),
+            from: "default",
         }
-        segments.push(&filename);
     }
+}
 
-    Ok(url)
+impl BinaryMirror {
+    pub(crate) fn from_config(env: &Env, config: &ProjectConfig) -> Result<Self, RubyInstallError> {
+        let (url, from) = match (env.get_string_lossy(MIRROR_ENV_KEY), &config.binary_mirror) {
+            (Some(url), _) if !url.trim().is_empty() => (url, MIRROR_ENV_KEY),
+            (_, Some(url)) => (url.clone(), PROJECT_TOML),
+            _ => return Ok(Self::default()),
+        };
+        let invalid = || RubyInstallError::InvalidMirror {
+            url: url.clone(),
+            from,
+        };
+
+        let url = Url::parse(url.trim()).map_err(|_| invalid())?;
+        if url.cannot_be_a_base() || !["http", "https", "file"].contains(&url.scheme()) {
+            return Err(invalid());
+        }
+        Ok(Self { url, from })
+    }
+
+    #[must_use]
+    pub(crate) fn is_default(&self) -> bool {
+        self.from == Self::default().from
+    }
 }
 
-pub(crate) fn download(
-    uri: impl AsRef<str>,
-    destination: impl AsRef<Path>,
-) -> Result<(), RubyInstallError> {
-    let mut response_reader = ureq::get(uri.as_ref())
-        .call()
-        .map_err(|err| RubyInstallError::RequestError(Box::new(err)))?
-        .into_reader();
+/// A Ruby build downloaded from a URL instead of the inventory, such as a patched Ruby
+///
+/// Set with the `HEROKU_RUBY_URL` and `HEROKU_RUBY_SHA256` environment variables or `ruby_url`
+/// and `ruby_sha256` in `project.toml`, the environment variables take precedence. Both the URL
+/// and checksum are required. The archive must use the same layout as the inventory binaries and
+/// contain the Ruby version the application requests.
+#[derive(Deserialize, Serialize, Debug, Clone, Eq, PartialEq)]
+pub(crate) struct CustomRuby {
+    pub(crate) url: String,
+    pub(crate) sha256: String,
+}
 
-    let mut destination_file = fs_err::File::create(destination.as_ref())
-        .map_err(RubyInstallError::CouldNotCreateDestinationFile)?;
+pub(crate) const CUSTOM_URL_ENV_KEY: &str = "HEROKU_RUBY_URL";
+pub(crate) const CUSTOM_SHA256_ENV_KEY: &str = "HEROKU_RUBY_SHA256";
 
-    io::copy(&mut response_reader, &mut destination_file)
-        .map_err(RubyInstallError::CouldNotWriteDestinationFile)?;
+impl CustomRuby {
+    /// The custom Ruby and where it was configured, `None` when not configured
+    pub(crate) fn from_config(
+        env: &Env,
+        config: &ProjectConfig,
+    ) -> Result<Option<(Self, &'static str)>, RubyInstallError> {
+        let env_value = |key| {
+            env.get_string_lossy(key)
+                .filter(|value| !value.trim().is_empty())
+        };
+        let (url, sha256, from) = match (
+            env_value(CUSTOM_URL_ENV_KEY),
+            env_value(CUSTOM_SHA256_ENV_KEY),
+        ) {
+            (None, None) => (
+                config.ruby_url.clone(),
+                config.ruby_sha256.clone(),
+                PROJECT_TOML,
+            ),
+            (url, sha256) => (url, sha256, CUSTOM_URL_ENV_KEY),
+        };
+        let invalid = |reason| RubyInstallError::InvalidCustomRuby { from, reason };
+        let (url, sha256) = match (url, sha256) {
+            (None, None) => return Ok(None),
+            (Some(url), Some(sha256)) => (url.trim().to_string(), sha256.trim().to_lowercase()),
+            (Some(_), None) => Err(invalid("a sha256 checksum is required with the URL"))?,
+            (None, Some(_)) => Err(invalid("a URL is required with the sha256 checksum"))?,
+        };
+        if sha256.len() != 64 || !sha256.chars().all(|c| c.is_ascii_hexdigit()) {
+            Err(invalid(
+                "the sha256 checksum must be 64 hexadecimal characters",
+            ))?;
+        }
+        let custom = Self { url, sha256 };
+        custom.parse_url().map_err(|()| {
+            invalid("expected an `https://`, `http://`, or `file://` URL to a `.tgz` file")
+        })?;
+        Ok(Some((custom, from)))
+    }
 
-    Ok(())
+    fn parse_url(&self) -> Result<Url, ()> {
+        let url = Url::parse(&self.url).map_err(|_| ())?;
+        if url.cannot_be_a_base() || !["http", "https", "file"].contains(&url.scheme()) {
+            return Err(());
+        }
+        Ok(url)
+    }
+
+    fn url(&self) -> Result<Url, RubyBuildpackError> {
+        self.parse_url()
+            .map_err(|()| RubyInstallError::InvalidCustomRuby {
+                from: "metadata",
+                reason: "expected an `https://`, `http://`, or `file://` URL",
+            })
+            .map_err(RubyBuildpackError::RubyInstallError)
+    }
 }
 
-pub(crate) fn untar(
-    path: impl AsRef<Path>,
-    destination: impl AsRef<Path>,
-) -> Result<(), RubyInstallError> {
-    let file = fs_err::File::open(path.as_ref()).map_err(RubyInstallError::CouldNotOpenFile)?;
+/// The URL of a Ruby binary
+///
+/// Binaries are stored by stack name, then by CPU architecture for targets with the `stack_arch`
+/// layout in `targets.toml` i.e. `<mirror>/heroku-24/arm64/ruby-3.3.6.tgz` or
+/// `<mirror>/heroku-22/ruby-3.3.6.tgz`.
+pub(crate) fn download_url(
+    mirror: &BinaryMirror,
+    target: &TargetId,
+    ruby: &ResolvedRuby,
+) -> Result<Url, RubyInstallError> {
+    let version = &ruby.version;
+    let filename = match ruby.engine {
+        RubyEngine::Mri => format!("ruby-{version}.tgz"),
+        engine => format!("ruby-{version}-{engine}-{}.tgz", ruby.engine_version),
+    };
+    let mut url = mirror.url.clone();
+    {
+        let mut segments =
+            url.path_segments_mut()
+                .map_err(|()| RubyInstallError::InvalidMirror {
+                    url: mirror.url.to_string(),
+                    from: mirror.from,
+                })?;
+
+        segments.pop_if_empty();
+        let target = target.target().map_err(RubyInstallError::TargetError)?;
+        segments.push(&target.stack);
+        match target.url_layout {
+            UrlLayout::Stack => {}
+            UrlLayout::StackArch => {
+                segments.push(&target.arch);
+            }
+        }
+        segments.push(&filename);
+    }
+
+    Ok(url)
+}
 
-    Archive::new(GzDecoder::new(file))
-        .unpack(destination.as_ref())
-        .map_err(RubyInstallError::CouldNotUnpack)
+fn untar(reader: impl Read, destination: &Path) -> Result<(), RubyInstallError> {
+    archive::unpack(GzDecoder::new(reader), destination, 0).map_err(RubyInstallError::UnpackError)
 }
 
 #[derive(thiserror::Error, Debug)]
 pub(crate) enum RubyInstallError {
+    #[error("Could not parse the Ruby inventory shipped with the buildpack: {0}")]
+    InventoryParseError(toml::de::Error),
+
+    #[error("Ruby {ruby} is not available for {target}")]
+    // Boxed to prevent `large_enum_variant` errors
+    NotInInventory {
+        ruby: ResolvedRuby,
+        target: Box<TargetId>,
+        suggestions: Box<Suggestions>,
+    },
+
+    #[error("No Ruby matching `{requirement}` from {file} is available for {target}")]
+    NoMatchingRuby {
+        requirement: String,
+        file: String,
+        target: Box<TargetId>,
+        suggestions: Box<Suggestions>,
+    },
+
+    #[error("Checksum of {url} failed verification. Expected sha256 {expected} got {actual}")]
+    ChecksumMismatch {
+        url: String,
+        expected: String,
+        actual: String,
+    },
+
     #[error("Unknown install target: {0}")]
     TargetError(TargetIdError),
 
-    #[error("Could not parse url {0}")]
-    UrlParseError(url::ParseError),
+    #[error("Invalid Ruby binary mirror `{url}` from {from}, expected an `https://`, `http://`, or `file://` URL")]
+    InvalidMirror { url: String, from: &'static str },
 
-    #[error("Invalid base url {0}")]
-    InvalidBaseUrl(String),
-
-    #[error("Could not open file: {0}")]
-    CouldNotOpenFile(std::io::Error),
+    #[error("Invalid custom Ruby from {from}, {reason}")]
+    InvalidCustomRuby {
+        from: &'static str,
+        reason: &'static str,
+    },
 
     #[error("Could not untar: {0}")]
     CouldNotUnpack(std::io::Error),
 
-    // Boxed to prevent `large_enum_variant` errors since `ureq::Error` is massive.
-    #[error("Download error: {0}")]
-    RequestError(Box<ureq::Error>),
+    #[error(transparent)]
+    UnpackError(UnpackError),
 
-    #[error("Could not create file: {0}")]
-    CouldNotCreateDestinationFile(std::io::Error),
+    #[error(transparent)]
+    DownloadError(DownloadError),
 
-    #[error("Could not write file: {0}")]
-    CouldNotWriteDestinationFile(std::io::Error),
+    #[error("Installed Ruby failed verification: {0}")]
+    VerifyFailed(Box<VerifyError>),
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::layers::shared::{strip_ansi, temp_build_context};
+    use crate::layers::shared::{cached_layer_write_metadata, strip_ansi, temp_build_context};
 
     use super::*;
+    use flate2::{write::GzEncoder, Compression};
+    use sha2::{Digest, Sha256};
 
     /// If this test fails due to a change you'll need to
     /// implement `TryMigrate` for the new layer data and add
@@ -257,6 +742,9 @@ mod tests {
             distro_version: String::from("22.04"),
             cpu_architecture: String::from("amd64"),
             ruby_version: ResolvedRubyVersion(String::from("3.1.3")),
+            ruby_engine: RubyEngine::Mri,
+            ruby_engine_version: String::from("3.1.3"),
+            custom_ruby: None,
         };
 
         let actual = toml::to_string(&metadata).unwrap();
@@ -265,6 +753,8 @@ distro_name = "ubuntu"
 distro_version = "22.04"
 cpu_architecture = "amd64"
 ruby_version = "3.1.3"
+ruby_engine = "ruby"
+ruby_engine_version = "3.1.3"
 "#
         .trim();
         assert_eq!(expected, actual.trim());
@@ -299,20 +789,280 @@ version = "3.1.3"
         assert_eq!(expected, deserialized);
     }
 
+    #[test]
+    fn metadata_migrate_v2_to_v3() {
+        let metadata = MetadataV2 {
+            distro_name: String::from("ubuntu"),
+            distro_version: String::from("22.04"),
+            cpu_architecture: String::from("amd64"),
+            ruby_version: ResolvedRubyVersion(String::from("2.6.8-jruby-9.3.6.0")),
+        };
+
+        let deserialized: MetadataV3 =
+            MetadataV3::try_from_str_migrations(&toml::to_string(&metadata).unwrap())
+                .unwrap()
+                .unwrap();
+
+        let expected = MetadataV3 {
+            distro_name: metadata.distro_name,
+            distro_version: metadata.distro_version,
+            cpu_architecture: metadata.cpu_architecture,
+            ruby_version: ResolvedRubyVersion(String::from("2.6.8")),
+            ruby_engine: RubyEngine::Jruby,
+            ruby_engine_version: String::from("9.3.6.0"),
+        };
+        assert_eq!(expected, deserialized);
+    }
+
+    #[test]
+    fn metadata_migrate_v3_to_v4() {
+        let metadata = MetadataV3 {
+            distro_name: String::from("ubuntu"),
+            distro_version: String::from("22.04"),
+            cpu_architecture: String::from("amd64"),
+            ruby_version: ResolvedRubyVersion(String::from("3.3.6")),
+            ruby_engine: RubyEngine::Mri,
+            ruby_engine_version: String::from("3.3.6"),
+        };
+
+        let deserialized: MetadataV4 =
+            MetadataV4::try_from_str_migrations(&toml::to_string(&metadata).unwrap())
+                .unwrap()
+                .unwrap();
+
+        let expected = MetadataV4 {
+            distro_name: metadata.distro_name,
+            distro_version: metadata.distro_version,
+            cpu_architecture: metadata.cpu_architecture,
+            ruby_version: metadata.ruby_version,
+            ruby_engine: metadata.ruby_engine,
+            ruby_engine_version: metadata.ruby_engine_version,
+            custom_ruby: None,
+        };
+        assert_eq!(expected, deserialized);
+    }
+
+    #[test]
+    fn test_custom_ruby() {
+        let sha256 = "a".repeat(64);
+        let mut env = Env::new();
+        let mut config = ProjectConfig::default();
+        assert_eq!(None, CustomRuby::from_config(&env, &config).unwrap());
+
+        config.ruby_url = Some(String::from("https://example.com/ruby-3.3.6.tgz"));
+        config.ruby_sha256 = Some(sha256.to_uppercase());
+        assert_eq!(
+            Some((
+                CustomRuby {
+                    url: String::from("https://example.com/ruby-3.3.6.tgz"),
+                    sha256: sha256.clone(),
+                },
+                PROJECT_TOML
+            )),
+            CustomRuby::from_config(&env, &config).unwrap()
+        );
+
+        env.insert(CUSTOM_URL_ENV_KEY, "file:///srv/ruby-3.3.6.tgz");
+        assert_eq!(
+            "Invalid custom Ruby from HEROKU_RUBY_URL, a sha256 checksum is required with the URL",
+            CustomRuby::from_config(&env, &config)
+                .unwrap_err()
+                .to_string()
+        );
+
+        env.insert(CUSTOM_SHA256_ENV_KEY, &sha256);
+        let (custom, from) = CustomRuby::from_config(&env, &config).unwrap().unwrap();
+        assert_eq!(CUSTOM_URL_ENV_KEY, from);
+        assert_eq!("file:///srv/ruby-3.3.6.tgz", custom.url);
+
+        env.insert(CUSTOM_SHA256_ENV_KEY, "abc");
+        assert!(matches!(
+            CustomRuby::from_config(&env, &config),
+            Err(RubyInstallError::InvalidCustomRuby { .. })
+        ));
+
+        env.insert(CUSTOM_URL_ENV_KEY, "ruby-3.3.6.tgz");
+        env.insert(CUSTOM_SHA256_ENV_KEY, &sha256);
+        assert!(matches!(
+            CustomRuby::from_config(&env, &config),
+            Err(RubyInstallError::InvalidCustomRuby { .. })
+        ));
+    }
+
     #[test]
     fn test_ruby_url() {
+        let target = TargetId {
+            cpu_architecture: String::from("amd64"),
+            distro_name: String::from("ubuntu"),
+            distro_version: String::from("22.04"),
+        };
+        let mirror = BinaryMirror::default();
+        let out = download_url(&mirror, &target, &ResolvedRuby::mri("2.7.4")).unwrap();
+        assert_eq!(
+            out.as_ref(),
+            "https://heroku-buildpack-ruby.s3.us-east-1.amazonaws.com/heroku-22/ruby-2.7.4.tgz",
+        );
+
         let out = download_url(
-            &TargetId {
-                cpu_architecture: String::from("amd64"),
-                distro_name: String::from("ubuntu"),
-                distro_version: String::from("22.04"),
+            &mirror,
+            &target,
+            &ResolvedRuby {
+                engine: RubyEngine::Jruby,
+                engine_version: String::from("9.3.6.0"),
+                version: ResolvedRubyVersion(String::from("2.6.8")),
             },
-            "2.7.4",
         )
         .unwrap();
         assert_eq!(
             out.as_ref(),
-            "https://heroku-buildpack-ruby.s3.us-east-1.amazonaws.com/heroku-22/ruby-2.7.4.tgz",
+            "https://heroku-buildpack-ruby.s3.us-east-1.amazonaws.com/heroku-22/ruby-2.6.8-jruby-9.3.6.0.tgz",
+        );
+    }
+
+    #[test]
+    fn test_binary_mirror() {
+        let target = TargetId {
+            cpu_architecture: String::from("arm64"),
+            distro_name: String::from("ubuntu"),
+            distro_version: String::from("24.04"),
+        };
+        let ruby = ResolvedRuby::mri("3.3.6");
+        let mut env = Env::new();
+        let config = ProjectConfig {
+            binary_mirror: Some(String::from("https://mirror.example.com/ruby/")),
+            ..ProjectConfig::default()
+        };
+
+        assert!(BinaryMirror::from_config(&env, &ProjectConfig::default())
+            .unwrap()
+            .is_default());
+
+        let mirror = BinaryMirror::from_config(&env, &config).unwrap();
+        assert_eq!(PROJECT_TOML, mirror.from);
+        assert_eq!(
+            "https://mirror.example.com/ruby/heroku-24/arm64/ruby-3.3.6.tgz",
+            download_url(&mirror, &target, &ruby).unwrap().as_str()
+        );
+
+        env.insert(MIRROR_ENV_KEY, "file:///srv/ruby");
+        let mirror = BinaryMirror::from_config(&env, &config).unwrap();
+        assert_eq!(MIRROR_ENV_KEY, mirror.from);
+        assert_eq!(
+            "file:///srv/ruby/heroku-24/arm64/ruby-3.3.6.tgz",
+            download_url(&mirror, &target, &ruby).unwrap().as_str()
+        );
+
+        for invalid in [
+            "mirror.example.com",
+            "ftp://mirror.example.com",
+            "mailto:ruby@example.com",
+        ] {
+            env.insert(MIRROR_ENV_KEY, invalid);
+            assert!(
+                matches!(
+                    BinaryMirror::from_config(&env, &config),
+                    Err(RubyInstallError::InvalidMirror { .. })
+                ),
+                "Expected {invalid:?} to be rejected"
+            );
+        }
+    }
+
+    fn tgz(files: &[(&str, &str)]) -> Vec<u8> {
+        let mut builder = tar::Builder::new(GzEncoder::new(Vec::new(), Compression::default()));
+        for (path, contents) in files {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(contents.len() as u64);
+            header.set_mode(0o755);
+            header.set_cksum();
+            builder
+                .append_data(&mut header, path, contents.as_bytes())
+                .unwrap();
+        }
+        builder.into_inner().unwrap().finish().unwrap()
+    }
+
+    #[test]
+    fn test_download_untar() {
+        let dir = tempfile::tempdir().unwrap();
+        let layer = dir.path().join("layer");
+        let source = dir.path().join("ruby-3.3.6.tgz");
+        let contents = tgz(&[("bin/ruby", "#!/usr/bin/env sh\n")]);
+        fs_err::create_dir(&layer).unwrap();
+        fs_err::write(&source, &contents).unwrap();
+
+        let url = Url::from_file_path(&source).unwrap();
+        let expected = format!("{:x}", Sha256::digest(&contents));
+        download_untar(
+            &mut Download::start(url.as_str()).unwrap(),
+            &url,
+            &expected.to_uppercase(),
+            &layer,
+        )
+        .unwrap();
+        assert!(layer.join("bin").join("ruby").exists());
+
+        let error = download_untar(
+            &mut Download::start(url.as_str()).unwrap(),
+            &url,
+            "abc",
+            &layer,
+        )
+        .unwrap_err();
+        assert_eq!(
+            format!("Checksum of {url} failed verification. Expected sha256 abc got {expected}"),
+            error.to_string()
+        );
+        assert_eq!(0, fs_err::read_dir(&layer).unwrap().count());
+    }
+
+    #[test]
+    fn test_verify_ruby() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let layer = tempfile::tempdir().unwrap();
+        let bin = layer.path().join("bin");
+        let metadata = Metadata {
+            ruby_version: ResolvedRubyVersion("3.4.0.preview2".to_string()),
+            ruby_engine: RubyEngine::Mri,
+            ruby_engine_version: "3.4.0.preview2".to_string(),
+            distro_name: "ubuntu".to_string(),
+            distro_version: "24.04".to_string(),
+            cpu_architecture: "amd64".to_string(),
+            custom_ruby: None,
+        };
+        let env = Env::from_current();
+        let fake_ruby = |script: &str| {
+            fs_err::create_dir_all(&bin).unwrap();
+            fs_err::write(bin.join("ruby"), format!("#!/usr/bin/env sh\n{script}\n")).unwrap();
+            fs_err::set_permissions(bin.join("ruby"), std::fs::Permissions::from_mode(0o755))
+                .unwrap();
+        };
+
+        assert!(matches!(
+            verify_ruby(&env, &metadata, layer.path()),
+            Err(VerifyError::Command(_))
+        ));
+
+        fake_ruby("echo 'ruby 3.4.0preview2 [x86_64-linux]'; echo 3.4.0");
+        assert_eq!(
+            "ruby 3.4.0preview2 [x86_64-linux]",
+            verify_ruby(&env, &metadata, layer.path()).unwrap()
+        );
+
+        fake_ruby("echo 'ruby 3.3.6 [x86_64-linux]'; echo 3.3.6");
+        assert_eq!(
+            "Expected Ruby 3.4.0.preview2 but `RUBY_VERSION` is `3.3.6`",
+            verify_ruby(&env, &metadata, layer.path())
+                .unwrap_err()
+                .to_string()
+        );
+
+        fake_ruby("echo 'cannot load such file -- openssl' >&2; exit 1");
+        let error = verify_ruby(&env, &metadata, layer.path()).unwrap_err();
+        assert!(
+            error.to_string().contains("cannot load such file"),
+            "{error}"
         );
     }
 
@@ -320,17 +1070,19 @@ version = "3.1.3"
     fn metadata_diff_messages() {
         let old = Metadata {
             ruby_version: ResolvedRubyVersion("3.5.3".to_string()),
+            ruby_engine: RubyEngine::Mri,
+            ruby_engine_version: "3.5.3".to_string(),
             distro_name: "ubuntu".to_string(),
             distro_version: "20.04".to_string(),
             cpu_architecture: "amd64".to_string(),
+            custom_ruby: None,
         };
         assert_eq!(old.diff(&old), Vec::<String>::new());
 
         let diff = Metadata {
             ruby_version: ResolvedRubyVersion("3.5.5".to_string()),
-            distro_name: old.distro_name.clone(),
-            distro_version: old.distro_version.clone(),
-            cpu_architecture: old.cpu_architecture.clone(),
+            ruby_engine_version: "3.5.5".to_string(),
+            ..old.clone()
         }
         .diff(&old);
         assert_eq!(
@@ -339,10 +1091,20 @@ version = "3.1.3"
         );
 
         let diff = Metadata {
-            ruby_version: old.ruby_version.clone(),
+            ruby_engine: RubyEngine::Truffleruby,
+            ruby_engine_version: "24.0.0".to_string(),
+            ..old.clone()
+        }
+        .diff(&old);
+        assert_eq!(
+            diff.iter().map(strip_ansi).collect::<Vec<String>>(),
+            vec!["Ruby version (`3.5.3` to `3.5.3 (truffleruby 24.0.0)`)".to_string()]
+        );
+
+        let diff = Metadata {
             distro_name: "alpine".to_string(),
             distro_version: "3.20.0".to_string(),
-            cpu_architecture: old.cpu_architecture.clone(),
+            ..old.clone()
         }
         .diff(&old);
 
@@ -352,16 +1114,45 @@ version = "3.1.3"
         );
 
         let diff = Metadata {
-            ruby_version: old.ruby_version.clone(),
-            distro_name: old.distro_name.clone(),
-            distro_version: old.distro_version.clone(),
             cpu_architecture: "arm64".to_string(),
+            ..old.clone()
         }
         .diff(&old);
         assert_eq!(
             diff.iter().map(strip_ansi).collect::<Vec<String>>(),
             vec!["CPU architecture (`amd64` to `arm64`)".to_string()]
         );
+
+        let custom = CustomRuby {
+            url: "https://example.com/ruby-3.5.3.tgz".to_string(),
+            sha256: "abc".to_string(),
+        };
+        let diff = Metadata {
+            custom_ruby: Some(custom.clone()),
+            ..old.clone()
+        }
+        .diff(&old);
+        assert_eq!(
+            diff.iter().map(strip_ansi).collect::<Vec<String>>(),
+            vec!["Ruby source (`inventory` to `https://example.com/ruby-3.5.3.tgz`)".to_string()]
+        );
+
+        let old = Metadata {
+            custom_ruby: Some(custom.clone()),
+            ..old.clone()
+        };
+        let diff = Metadata {
+            custom_ruby: Some(CustomRuby {
+                sha256: "def".to_string(),
+                ..custom.clone()
+            }),
+            ..old.clone()
+        }
+        .diff(&old);
+        assert_eq!(
+            diff.iter().map(strip_ansi).collect::<Vec<String>>(),
+            vec!["Ruby source checksum (`abc` to `def`)".to_string()]
+        );
     }
 
     #[test]
@@ -370,9 +1161,12 @@ version = "3.1.3"
         let context = temp_build_context::<RubyBuildpack>(temp.path());
         let old = Metadata {
             ruby_version: ResolvedRubyVersion("2.7.2".to_string()),
+            ruby_engine: RubyEngine::Mri,
+            ruby_engine_version: "2.7.2".to_string(),
             distro_name: "ubuntu".to_string(),
             distro_version: "20.04".to_string(),
             cpu_architecture: "x86_64".to_string(),
+            custom_ruby: None,
         };
         let differences = old.diff(&old);
         assert_eq!(differences, Vec::<String>::new());
@@ -384,6 +1178,7 @@ version = "3.1.3"
 
         let now = Metadata {
             ruby_version: ResolvedRubyVersion("3.0.0".to_string()),
+            ruby_engine_version: "3.0.0".to_string(),
             ..old.clone()
         };
         let differences = now.diff(&old);
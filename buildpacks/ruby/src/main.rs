@@ -1,12 +1,16 @@
 use bullet_stream::{style, Print};
 use commons::cache::CacheError;
-use commons::gemfile_lock::GemfileLock;
+use commons::gemfile_lock::{GemfileLock, GemfileLockError, RubyEngine, RubyVersion};
 use commons::metadata_digest::MetadataDigest;
+use commons::ruby_requirement::RubyRequirementError;
+use commons::ruby_version_file::VersionFile;
 use core::str::FromStr;
 use fs_err::PathExt;
 use fun_run::CmdError;
+use gemfile_variant::GemfileVariant;
 use layers::{
-    metrics_agent_install::MetricsAgentInstallError, ruby_install_layer::RubyInstallError,
+    metrics_agent_install::MetricsAgentInstallError,
+    ruby_install_layer::{BinaryMirror, CustomRuby, RubyInstallError},
 };
 use libcnb::build::{BuildContext, BuildResult, BuildResultBuilder};
 use libcnb::data::build_plan::BuildPlanBuilder;
@@ -16,15 +20,31 @@ use libcnb::generic::{GenericMetadata, GenericPlatform};
 use libcnb::layer_env::Scope;
 use libcnb::Platform;
 use libcnb::{buildpack_main, Buildpack};
+use project_config::{ProjectConfig, ProjectConfigError};
+use resolve_ruby::{RubySource, VersionCheck, VersionCheckError};
+use ruby_inventory::Inventory;
+use ruby_lifecycle::Lifecycle;
 use std::io::stdout;
+use target_id::TargetId;
+use warn_later::WarnLater;
 
+mod archive;
+mod bundle_parallelism;
+mod download;
+mod gem_credentials;
 mod gem_list;
+mod gemfile_variant;
 mod layers;
+mod project_config;
 mod rake_status;
 mod rake_task_detect;
+mod resolve_ruby;
+mod ruby_inventory;
+mod ruby_lifecycle;
 mod steps;
 mod target_id;
 mod user_errors;
+mod warn_later;
 
 #[cfg(test)]
 use libcnb_test as _;
@@ -46,6 +66,9 @@ enum DetectError {
 
     #[error("Cannot read yarn.lock {0}")]
     YarnLock(std::io::Error),
+
+    #[error("Cannot read .ruby-version {0}")]
+    RubyVersion(std::io::Error),
 }
 
 impl Buildpack for RubyBuildpack {
@@ -56,50 +79,46 @@ impl Buildpack for RubyBuildpack {
     fn detect(&self, context: DetectContext<Self>) -> libcnb::Result<DetectResult, Self::Error> {
         let mut plan_builder = BuildPlanBuilder::new().provides("ruby");
 
-        let lockfile = context.app_dir.join("Gemfile.lock");
-
-        if lockfile
-            .fs_err_try_exists()
-            .map_err(DetectError::GemfileLock)
+        if let Some(gemfile_variant) = GemfileVariant::from_dir(&context.app_dir)
+            .map_err(DetectError::Gemfile)
             .map_err(RubyBuildpackError::BuildpackDetectionError)?
         {
             plan_builder = plan_builder.requires("ruby");
 
-            if context
-                .app_dir
-                .join("package.json")
-                .fs_err_try_exists()
-                .map_err(DetectError::PackageJson)
-                .map_err(RubyBuildpackError::BuildpackDetectionError)?
-            {
-                plan_builder = plan_builder.requires("node");
-            }
-
-            if context
-                .app_dir
-                .join("yarn.lock")
+            let lockfile = context.app_dir.join(gemfile_variant.lockfile());
+            if lockfile
                 .fs_err_try_exists()
-                .map_err(DetectError::YarnLock)
-                .map_err(RubyBuildpackError::BuildpackDetectionError)?
-            {
-                plan_builder = plan_builder.requires("yarn");
-            }
-
-            if fs_err::read_to_string(lockfile)
                 .map_err(DetectError::GemfileLock)
-                .map_err(RubyBuildpackError::BuildpackDetectionError)
-                .map(needs_java)?
+                .map_err(RubyBuildpackError::BuildpackDetectionError)?
             {
-                plan_builder = plan_builder.requires("jdk");
+                if context
+                    .app_dir
+                    .join("package.json")
+                    .fs_err_try_exists()
+                    .map_err(DetectError::PackageJson)
+                    .map_err(RubyBuildpackError::BuildpackDetectionError)?
+                {
+                    plan_builder = plan_builder.requires("node");
+                }
+
+                if context
+                    .app_dir
+                    .join("yarn.lock")
+                    .fs_err_try_exists()
+                    .map_err(DetectError::YarnLock)
+                    .map_err(RubyBuildpackError::BuildpackDetectionError)?
+                {
+                    plan_builder = plan_builder.requires("yarn");
+                }
+
+                if fs_err::read_to_string(&lockfile)
+                    .map_err(DetectError::GemfileLock)
+                    .map_err(RubyBuildpackError::BuildpackDetectionError)
+                    .map(needs_java)?
+                {
+                    plan_builder = plan_builder.requires("jdk");
+                }
             }
-        } else if context
-            .app_dir
-            .join("Gemfile")
-            .fs_err_try_exists()
-            .map_err(DetectError::Gemfile)
-            .map_err(RubyBuildpackError::BuildpackDetectionError)?
-        {
-            plan_builder = plan_builder.requires("ruby");
         }
 
         DetectResultBuilder::pass()
@@ -110,23 +129,77 @@ impl Buildpack for RubyBuildpack {
     #[allow(clippy::too_many_lines)]
     fn build(&self, context: BuildContext<Self>) -> libcnb::Result<BuildResult, Self::Error> {
         let mut build_output = Print::new(stdout()).h2("Heroku Ruby Buildpack");
+        let mut warn_later = WarnLater::new();
 
         // ## Set default environment
-        let (mut env, store) =
+        let (mut env, mut store) =
             crate::steps::default_env(&context, &context.platform.env().clone())?;
 
         // Gather static information about project
-        let lockfile = context.app_dir.join("Gemfile.lock");
+        let gemfile_variant = GemfileVariant::from_dir(&context.app_dir)
+            .map_err(DetectError::Gemfile)
+            .map_err(RubyBuildpackError::BuildpackDetectionError)?
+            .unwrap_or(GemfileVariant::Gemfile);
+        let gemfile = context.app_dir.join(gemfile_variant.gemfile());
+        let lockfile = context.app_dir.join(gemfile_variant.lockfile());
         let lockfile_contents = fs_err::read_to_string(&lockfile)
-            .map_err(|error| RubyBuildpackError::MissingGemfileLock(lockfile, error))?;
-        let gemfile_lock = GemfileLock::from_str(&lockfile_contents).expect("Infallible");
+            .map_err(|error| RubyBuildpackError::MissingGemfileLock(lockfile.clone(), error))?;
+        let gemfile_lock = GemfileLock::from_str(&lockfile_contents)
+            .map_err(|error| RubyBuildpackError::GemfileLockParseError(lockfile.clone(), error))?
+            .with_version_files(
+                VersionFile::read_all(&context.app_dir)
+                    .map_err(DetectError::RubyVersion)
+                    .map_err(RubyBuildpackError::BuildpackDetectionError)?,
+            );
         let bundler_version = gemfile_lock.resolve_bundler("2.4.5");
-        let ruby_version = gemfile_lock.resolve_ruby("3.1.3");
+        let project_config = ProjectConfig::read(&context.app_dir)
+            .map_err(RubyBuildpackError::ProjectConfigError)?;
+        let binary_mirror = BinaryMirror::from_config(context.platform.env(), &project_config)
+            .map_err(RubyBuildpackError::RubyInstallError)?;
+        let custom_ruby = CustomRuby::from_config(context.platform.env(), &project_config)
+            .map_err(RubyBuildpackError::RubyInstallError)?;
+        let inventory = Inventory::shipped()
+            .map_err(RubyInstallError::InventoryParseError)
+            .map_err(RubyBuildpackError::RubyInstallError)?;
+        let target_id = TargetId {
+            distro_name: context.target.distro_name.clone(),
+            distro_version: context.target.distro_version.clone(),
+            cpu_architecture: context.target.arch.clone(),
+        };
+        if let Some(warning) = target_id
+            .target()
+            .ok()
+            .and_then(|target| target.deprecation_warning(ruby_lifecycle::today()))
+        {
+            build_output = build_output.warning(&warning);
+            warn_later.push(warning);
+        }
+        let default_ruby = steps::sticky_default_ruby(
+            context.store.as_ref(),
+            &inventory,
+            &target_id,
+            steps::DEFAULT_RUBY_VERSION,
+        );
+        let (version_check, version_check_from) =
+            VersionCheck::from_config(context.platform.env(), &project_config)
+                .map_err(RubyBuildpackError::RubyVersionCheckError)?;
+        let ruby_choice = resolve_ruby::resolve(
+            &context.app_dir,
+            gemfile_variant,
+            &gemfile_lock,
+            &inventory,
+            &target_id,
+            &default_ruby.version,
+            version_check,
+        )?;
+        let ruby = ruby_choice.ruby.clone();
+        let uses_default_ruby = ruby_choice.source == RubySource::Default;
+        steps::record_default_ruby(&mut store, uses_default_ruby.then_some(&ruby.version));
 
         // ## Install metrics agent
         build_output = {
             let bullet = build_output.bullet("Metrics agent");
-            if lockfile_contents.contains("barnes") {
+            if gemfile_lock.has_gem("barnes") {
                 layers::metrics_agent_install::handle_metrics_agent_layer(&context, bullet)?.done()
             } else {
                 bullet
@@ -140,25 +213,99 @@ impl Buildpack for RubyBuildpack {
 
         // ## Install executable ruby version
         (build_output, env) = {
-            let bullet = build_output.bullet(format!(
+            let mut bullet = build_output.bullet(format!(
                 "Ruby version {} from {}",
-                style::value(ruby_version.to_string()),
-                style::value(gemfile_lock.ruby_source())
+                style::value(ruby.to_string()),
+                style::value(ruby_choice.source.to_string())
             ));
+            if let Some(requirement) = &ruby_choice.requirement {
+                bullet = bullet.sub_bullet(format!(
+                    "Newest Ruby matching {} for {} {} ({})",
+                    style::value(requirement.to_string()),
+                    target_id.distro_name,
+                    target_id.distro_version,
+                    target_id.cpu_architecture
+                ));
+            }
+            if let Some(from) = version_check_from {
+                bullet = bullet.sub_bullet(format!(
+                    "Ruby version check {} from {}",
+                    style::value(version_check.as_str()),
+                    style::value(from)
+                ));
+            }
+            if let Some((custom, from)) = &custom_ruby {
+                bullet = bullet.sub_bullet(format!(
+                    "Using custom Ruby {} from {}",
+                    style::url(&custom.url),
+                    style::value(*from)
+                ));
+            } else if !binary_mirror.is_default() {
+                bullet = bullet.sub_bullet(format!(
+                    "Using binary mirror {} from {}",
+                    style::url(binary_mirror.url.as_str()),
+                    style::value(binary_mirror.from)
+                ));
+            }
+            if let (true, Some(newer)) = (uses_default_ruby, &default_ruby.newer) {
+                let warning = format!(
+                    "A newer default Ruby version {newer} is available. This application keeps \
+                     using Ruby {ruby}, the default when it was first built, so that deploys do \
+                     not change its Ruby version unexpectedly.\n\n\
+                     To use Ruby {newer}, add {directive} to your {gemfile} and run \
+                     {bundle_install} to record it in your {lockfile}.",
+                    directive = style::value(format!("ruby \"{newer}\"")),
+                    gemfile = style::value(gemfile_variant.gemfile()),
+                    bundle_install = style::command("bundle install"),
+                    lockfile = style::value(gemfile_variant.lockfile()),
+                );
+                bullet = bullet.warning(&warning);
+                warn_later.push(warning);
+            }
+            for warning in &ruby_choice.warnings {
+                bullet = bullet.warning(warning);
+                warn_later.push(warning);
+            }
+            match Lifecycle::shipped() {
+                Ok(lifecycle) => {
+                    for warning in lifecycle.warnings(&ruby, ruby_lifecycle::today()) {
+                        bullet = bullet.warning(warning.to_string());
+                        warn_later.push(warning.to_string());
+                    }
+                }
+                Err(error) => {
+                    bullet = bullet.warning(format!(
+                        "Skipping Ruby end-of-life check, cannot parse lifecycle data: {error}"
+                    ));
+                }
+            }
             let (bullet, layer_env) = layers::ruby_install_layer::handle(
                 &context,
+                &env,
                 bullet,
                 &layers::ruby_install_layer::Metadata {
                     distro_name: context.target.distro_name.clone(),
                     distro_version: context.target.distro_version.clone(),
                     cpu_architecture: context.target.arch.clone(),
-                    ruby_version: ruby_version.clone(),
+                    ruby_version: ruby.version.clone(),
+                    ruby_engine: ruby.engine,
+                    ruby_engine_version: ruby.engine_version.clone(),
+                    custom_ruby: custom_ruby.as_ref().map(|(custom, _)| custom.clone()),
                 },
+                &inventory,
+                &binary_mirror,
             )?;
 
             (bullet.done(), layer_env.apply(Scope::Build, &env))
         };
 
+        // ## Ruby version defaults
+        (build_output, env) = {
+            let bullet = build_output.bullet("Ruby runtime defaults");
+            let (bullet, env) = steps::versioned_env_defaults(&context, bullet, &ruby, &env)?;
+            (bullet.done(), env)
+        };
+
         // ## Setup bundler
         (build_output, env) = {
             let bullet = build_output.bullet(format!(
@@ -180,7 +327,12 @@ impl Buildpack for RubyBuildpack {
 
         // ## Bundle install
         (build_output, env) = {
-            let bullet = build_output.bullet("Bundle install gems");
+            let (bullet, without, launch_without) = layers::bundle_install_layer::gem_groups(
+                build_output.bullet("Bundle install gems"),
+                context.platform.env(),
+                &project_config,
+                &gemfile,
+            )?;
             let (bullet, layer_env) = layers::bundle_install_layer::handle(
                 &context,
                 &env,
@@ -189,16 +341,18 @@ impl Buildpack for RubyBuildpack {
                     distro_name: context.target.distro_name.clone(),
                     distro_version: context.target.distro_version.clone(),
                     cpu_architecture: context.target.arch.clone(),
-                    ruby_version: ruby_version.clone(),
+                    ruby_version: ruby.version.clone(),
+                    ruby_engine: ruby.engine,
+                    ruby_engine_version: ruby.engine_version.clone(),
                     force_bundle_install_key: String::from(
                         crate::layers::bundle_install_layer::FORCE_BUNDLE_INSTALL_CACHE_KEY,
                     ),
-                    digest: MetadataDigest::new_env_files(
+                    without: without.clone(),
+                    launch_without,
+                    digest: MetadataDigest::new_env_files_ignoring(
                         &context.platform,
-                        &[
-                            &context.app_dir.join("Gemfile"),
-                            &context.app_dir.join("Gemfile.lock"),
-                        ],
+                        &[&gemfile, &lockfile],
+                        &bundle_parallelism::BundleParallelism::ENV_KEYS,
                     )
                     .map_err(|error| match error {
                         commons::metadata_digest::DigestError::CannotReadFile(path, error) => {
@@ -206,7 +360,7 @@ impl Buildpack for RubyBuildpack {
                         }
                     })?,
                 },
-                &BundleWithout::new("development:test"),
+                &gemfile,
             )?;
 
             (bullet.done(), layer_env.apply(Scope::Build, &env))
@@ -239,7 +393,7 @@ impl Buildpack for RubyBuildpack {
             }
             .done()
         };
-        build_output.done();
+        warn_later.warn_now(build_output).done();
 
         if let Some(default_process) = default_process {
             BuildResultBuilder::new()
@@ -256,9 +410,17 @@ impl Buildpack for RubyBuildpack {
     }
 }
 
+/// A lockfile that cannot be parsed does not need Java, the parse error is reported at build
 fn needs_java(gemfile_lock: impl AsRef<str>) -> bool {
-    let java_regex = regex::Regex::new(r"\(jruby ").expect("clippy");
-    java_regex.is_match(gemfile_lock.as_ref())
+    GemfileLock::from_str(gemfile_lock.as_ref()).is_ok_and(|gemfile_lock| {
+        matches!(
+            &gemfile_lock.ruby_version,
+            RubyVersion::Explicit {
+                engine: RubyEngine::Jruby,
+                ..
+            }
+        )
+    })
 }
 
 #[derive(Debug)]
@@ -269,6 +431,10 @@ pub(crate) enum RubyBuildpackError {
     RubyInstallError(RubyInstallError),
     MetricsAgentError(MetricsAgentInstallError),
     MissingGemfileLock(std::path::PathBuf, std::io::Error),
+    ProjectConfigError(ProjectConfigError),
+    GemfileLockParseError(std::path::PathBuf, GemfileLockError),
+    RubyRequirementError(std::path::PathBuf, RubyRequirementError),
+    RubyVersionCheckError(VersionCheckError),
     InAppDirCacheError(CacheError),
     BundleInstallDigestError(std::path::PathBuf, std::io::Error),
     BundleInstallCommandError(CmdError),
@@ -284,31 +450,34 @@ impl From<RubyBuildpackError> for libcnb::Error<RubyBuildpackError> {
 
 buildpack_main!(RubyBuildpack);
 
-#[derive(serde::Deserialize, serde::Serialize, Debug, Clone, PartialEq, Eq)]
-struct BundleWithout(String);
-
-impl BundleWithout {
-    fn new(without: impl AsRef<str>) -> Self {
-        Self(String::from(without.as_ref()))
-    }
-    fn as_str(&self) -> &str {
-        &self.0
-    }
-}
-
 #[cfg(test)]
 mod test {
     use super::*;
 
     #[test]
     fn test_needs_java() {
-        let gemfile_lock = r"";
-        assert!(!needs_java(gemfile_lock));
+        assert!(!needs_java(""));
+
+        assert!(needs_java(
+            r"
+RUBY VERSION
+   ruby 2.5.7p001 (jruby 9.2.13.0)
+"
+        ));
+
+        assert!(!needs_java(
+            r"
+RUBY VERSION
+   ruby 3.2.2p0 (truffleruby 24.0.0)
+"
+        ));
 
-        let gemfile_lock = r"
+        assert!(!needs_java(
+            r"
+<<<<<<< HEAD
 RUBY VERSION
    ruby 2.5.7p001 (jruby 9.2.13.0)
-";
-        assert!(needs_java(gemfile_lock));
+"
+        ));
     }
 }
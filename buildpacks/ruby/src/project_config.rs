@@ -0,0 +1,173 @@
+//! Buildpack configuration from the application's `project.toml`
+//!
+//! Settings live in the `[com.heroku.buildpacks.ruby]` table of a
+//! [project descriptor](https://buildpacks.io/docs/reference/config/project-descriptor/):
+//!
+//! ```toml
+//! [_]
+//! schema-version = "0.2"
+//!
+//! [com.heroku.buildpacks.ruby]
+//! binary_mirror = "https://ruby-mirror.example.com"
+//! ruby_version_check = "strict"
+//! bundle_without = ["development", "test"]
+//! build_only_groups = ["assets"]
+//! ```
+//!
+//! A missing file or table is the same as an empty configuration. Unknown keys in the table are
+//! rejected so that a typo does not silently fall back to the default behavior. Settings that can
+//! also be set with an environment variable prefer the environment variable.
+use crate::resolve_ruby::VersionCheck;
+use serde::Deserialize;
+use std::io;
+use std::path::{Path, PathBuf};
+
+pub(crate) const PROJECT_TOML: &str = "project.toml";
+
+#[derive(Debug, Default, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct ProjectConfig {
+    /// Base URL to download Ruby binaries from instead of the default S3 bucket, `file://` URLs
+    /// read from a local directory
+    pub(crate) binary_mirror: Option<String>,
+    /// URL of a custom Ruby build to install instead of the inventory, requires `ruby_sha256`
+    pub(crate) ruby_url: Option<String>,
+    /// sha256 checksum of the custom Ruby build at `ruby_url`
+    pub(crate) ruby_sha256: Option<String>,
+    /// Fail the build when Ruby version sources disagree instead of warning
+    pub(crate) ruby_version_check: Option<VersionCheck>,
+    /// Gem groups `bundle install` leaves out, `development` and `test` when not set
+    pub(crate) bundle_without: Option<Vec<String>>,
+    /// Gem groups such as `assets` that are installed for the build and left out at launch
+    pub(crate) build_only_groups: Option<Vec<String>>,
+}
+
+#[derive(thiserror::Error, Debug)]
+pub(crate) enum ProjectConfigError {
+    #[error("Cannot read project.toml: {0}")]
+    CannotRead(io::Error),
+
+    #[error("Cannot parse {0}: {1}")]
+    CannotParse(PathBuf, toml::de::Error),
+}
+
+impl ProjectConfig {
+    pub(crate) fn read(app_dir: &Path) -> Result<Self, ProjectConfigError> {
+        let path = app_dir.join(PROJECT_TOML);
+        match fs_err::read_to_string(&path) {
+            Ok(contents) => Self::from_project_toml(&contents)
+                .map_err(|error| ProjectConfigError::CannotParse(path, error)),
+            Err(error) if error.kind() == io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(error) => Err(ProjectConfigError::CannotRead(error)),
+        }
+    }
+
+    fn from_project_toml(contents: &str) -> Result<Self, toml::de::Error> {
+        let project = toml::Value::Table(toml::from_str::<toml::Table>(contents)?);
+        ["com", "heroku", "buildpacks", "ruby"]
+            .iter()
+            .try_fold(&project, |value, key| value.get(key))
+            .cloned()
+            .map_or_else(|| Ok(Self::default()), toml::Value::try_into)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use indoc::indoc;
+
+    #[test]
+    fn test_missing_file() {
+        let app_dir = tempfile::tempdir().unwrap();
+        assert_eq!(
+            ProjectConfig::default(),
+            ProjectConfig::read(app_dir.path()).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_other_tables_are_ignored() {
+        let config = ProjectConfig::from_project_toml(indoc! {r#"
+            [_]
+            schema-version = "0.2"
+
+            [com.heroku.buildpacks.deb-packages]
+            install = ["git"]
+        "#})
+        .unwrap();
+        assert_eq!(ProjectConfig::default(), config);
+    }
+
+    #[test]
+    fn test_binary_mirror() {
+        let config = ProjectConfig::from_project_toml(indoc! {r#"
+            [com.heroku.buildpacks.ruby]
+            binary_mirror = "file:///srv/ruby"
+        "#})
+        .unwrap();
+        assert_eq!(Some(String::from("file:///srv/ruby")), config.binary_mirror);
+    }
+
+    #[test]
+    fn test_ruby_version_check() {
+        let config = ProjectConfig::from_project_toml(indoc! {r#"
+            [com.heroku.buildpacks.ruby]
+            ruby_version_check = "strict"
+        "#})
+        .unwrap();
+        assert_eq!(Some(VersionCheck::Strict), config.ruby_version_check);
+
+        assert!(ProjectConfig::from_project_toml(indoc! {r#"
+            [com.heroku.buildpacks.ruby]
+            ruby_version_check = "error"
+        "#})
+        .is_err());
+    }
+
+    #[test]
+    fn test_bundle_groups() {
+        let config = ProjectConfig::from_project_toml(indoc! {r#"
+            [com.heroku.buildpacks.ruby]
+            bundle_without = ["development", "test", "ci"]
+            build_only_groups = ["assets"]
+        "#})
+        .unwrap();
+        assert_eq!(
+            Some(vec![
+                String::from("development"),
+                String::from("test"),
+                String::from("ci")
+            ]),
+            config.bundle_without
+        );
+        assert_eq!(Some(vec![String::from("assets")]), config.build_only_groups);
+    }
+
+    #[test]
+    fn test_custom_ruby() {
+        let config = ProjectConfig::from_project_toml(indoc! {r#"
+            [com.heroku.buildpacks.ruby]
+            ruby_url = "https://example.com/ruby-3.3.6.tgz"
+            ruby_sha256 = "abc"
+        "#})
+        .unwrap();
+        assert_eq!(
+            Some(String::from("https://example.com/ruby-3.3.6.tgz")),
+            config.ruby_url
+        );
+        assert_eq!(Some(String::from("abc")), config.ruby_sha256);
+    }
+
+    #[test]
+    fn test_unknown_keys_are_rejected() {
+        let app_dir = tempfile::tempdir().unwrap();
+        fs_err::write(
+            app_dir.path().join(PROJECT_TOML),
+            "[com.heroku.buildpacks.ruby]\nbinary_mirorr = \"https://example.com\"\n",
+        )
+        .unwrap();
+        let error = ProjectConfig::read(app_dir.path()).unwrap_err();
+        assert!(error.to_string().contains("binary_mirorr"), "{error}");
+    }
+}
@@ -0,0 +1,657 @@
+//! Determines which Ruby to install
+//!
+//! Sources are checked in order:
+//!
+//! - The `RUBY VERSION` from the `Gemfile.lock`.
+//! - A version requirement such as `ruby "~> 3.3"` in the `Gemfile`, when the `Gemfile.lock` does
+//!   not lock a Ruby version.
+//! - A version requirement in `.ruby-version` such as `3.3` or `3.3.6`, then `.tool-versions`
+//!   (see `GemfileLock::version_files`).
+//! - The default Ruby version.
+//!
+//! Requirements resolve to the newest Ruby in the inventory that satisfies them. When the
+//! inventory does not list Ruby for the target, an exact version is installed as is and other
+//! requirements fall back to the default Ruby version if it satisfies them.
+//!
+//! Before Ruby is installed, every source is checked against the chosen Ruby so that a
+//! `.ruby-version` or `Gemfile` that disagrees with the `Gemfile.lock` does not go unnoticed, and
+//! bundler does not fail late during `bundle install`. Conflicts are warnings by default, or fail
+//! the build with [`VersionCheck::Strict`].
+use crate::gemfile_variant::GemfileVariant;
+use crate::layers::ruby_install_layer::RubyInstallError;
+use crate::project_config::{ProjectConfig, PROJECT_TOML};
+use crate::ruby_inventory::Inventory;
+use crate::target_id::TargetId;
+use crate::{DetectError, RubyBuildpackError};
+use bullet_stream::style;
+use commons::gem_version::GemVersion;
+use commons::gemfile::{Gemfile, RubyDirective};
+use commons::gemfile_lock::{GemfileLock, ResolvedRuby, RubyVersion};
+use commons::ruby_requirement::RubyRequirement;
+use libcnb::Env;
+use serde::Deserialize;
+use std::fmt::Display;
+use std::io;
+use std::path::Path;
+use std::str::FromStr;
+
+#[derive(Debug, Clone)]
+pub(crate) struct RubyChoice {
+    pub(crate) ruby: ResolvedRuby,
+    pub(crate) source: RubySource,
+    /// Set when the Ruby was resolved from a requirement against the inventory
+    pub(crate) requirement: Option<RubyRequirement>,
+    /// Sources that disagree with the chosen Ruby
+    pub(crate) warnings: Vec<String>,
+}
+
+/// Where the Ruby version of a build came from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum RubySource {
+    /// The application does not specify a Ruby version
+    Default,
+    /// File name the Ruby version came from i.e. `Gemfile.lock` or `.ruby-version`
+    File(String),
+}
+
+impl std::fmt::Display for RubySource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RubySource::Default => write!(f, "default"),
+            RubySource::File(file) => write!(f, "{file}"),
+        }
+    }
+}
+
+pub(crate) const VERSION_CHECK_ENV_KEY: &str = "HEROKU_RUBY_VERSION_CHECK";
+
+/// How conflicts between Ruby version sources are reported
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum VersionCheck {
+    /// Print a warning and continue with the chosen Ruby
+    #[default]
+    Warn,
+    /// Fail the build before Ruby is installed
+    Strict,
+}
+
+impl VersionCheck {
+    pub(crate) fn as_str(self) -> &'static str {
+        match self {
+            Self::Warn => "warn",
+            Self::Strict => "strict",
+        }
+    }
+
+    /// Reads `HEROKU_RUBY_VERSION_CHECK` or `ruby_version_check` from `project.toml`, along with
+    /// where the setting came from
+    pub(crate) fn from_config(
+        env: &Env,
+        config: &ProjectConfig,
+    ) -> Result<(Self, Option<&'static str>), VersionCheckError> {
+        match env.get_string_lossy(VERSION_CHECK_ENV_KEY) {
+            Some(value) if !value.trim().is_empty() => {
+                match value.trim().to_ascii_lowercase().as_str() {
+                    "warn" => Ok((Self::Warn, Some(VERSION_CHECK_ENV_KEY))),
+                    "strict" => Ok((Self::Strict, Some(VERSION_CHECK_ENV_KEY))),
+                    _ => Err(VersionCheckError::InvalidSetting(value)),
+                }
+            }
+            _ => Ok(config
+                .ruby_version_check
+                .map_or((Self::default(), None), |check| (check, Some(PROJECT_TOML)))),
+        }
+    }
+}
+
+/// A Ruby version source that disagrees with another one
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct VersionConflict {
+    /// File name such as `.ruby-version`
+    pub(crate) file: String,
+    /// Version or requirement as written in `file`
+    pub(crate) declared: String,
+    pub(crate) other_file: String,
+    pub(crate) other: String,
+}
+
+impl Display for VersionConflict {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{file} declares Ruby {declared} but {other_file} declares Ruby {other}",
+            file = self.file,
+            declared = self.declared,
+            other_file = self.other_file,
+            other = self.other,
+        )
+    }
+}
+
+#[derive(thiserror::Error, Debug)]
+pub(crate) enum VersionCheckError {
+    #[error("Invalid {VERSION_CHECK_ENV_KEY} value {0:?}, expected `warn` or `strict`")]
+    InvalidSetting(String),
+
+    #[error("Ruby version sources disagree: {}", .0.iter().map(ToString::to_string).collect::<Vec<_>>().join(", "))]
+    Conflicts(Vec<VersionConflict>),
+}
+
+pub(crate) fn resolve(
+    app_dir: &Path,
+    gemfile_variant: GemfileVariant,
+    gemfile_lock: &GemfileLock,
+    inventory: &Inventory,
+    target: &TargetId,
+    default: &str,
+    check: VersionCheck,
+) -> Result<RubyChoice, RubyBuildpackError> {
+    let gemfile_requirement = read_optional(&app_dir.join(gemfile_variant.gemfile()))
+        .map_err(DetectError::Gemfile)
+        .map_err(RubyBuildpackError::BuildpackDetectionError)?
+        .and_then(|contents| gemfile_ruby_directive(&contents))
+        .map(|requirement| {
+            RubyRequirement::from_str(&requirement).map_err(|error| {
+                RubyBuildpackError::RubyRequirementError(
+                    app_dir.join(gemfile_variant.gemfile()),
+                    error,
+                )
+            })
+        })
+        .transpose()?;
+
+    let mut choice = choose(
+        app_dir,
+        gemfile_variant,
+        gemfile_requirement.clone(),
+        gemfile_lock,
+        inventory,
+        target,
+        default,
+    )?;
+    let conflicts = conflicts(
+        gemfile_variant,
+        gemfile_requirement.as_ref(),
+        gemfile_lock,
+        &choice,
+    );
+    match check {
+        VersionCheck::Strict if !conflicts.is_empty() => Err(
+            RubyBuildpackError::RubyVersionCheckError(VersionCheckError::Conflicts(conflicts)),
+        ),
+        _ => {
+            choice.warnings = conflicts.iter().map(warning).collect();
+            Ok(choice)
+        }
+    }
+}
+
+fn choose(
+    app_dir: &Path,
+    gemfile_variant: GemfileVariant,
+    gemfile_requirement: Option<RubyRequirement>,
+    gemfile_lock: &GemfileLock,
+    inventory: &Inventory,
+    target: &TargetId,
+    default: &str,
+) -> Result<RubyChoice, RubyBuildpackError> {
+    if let RubyVersion::Explicit { .. } = gemfile_lock.ruby_version {
+        return Ok(RubyChoice {
+            ruby: gemfile_lock.resolve_ruby(default),
+            source: RubySource::File(gemfile_variant.lockfile().to_string()),
+            requirement: None,
+            warnings: Vec::new(),
+        });
+    }
+
+    if let Some(requirement) = gemfile_requirement {
+        return from_inventory(
+            inventory,
+            target,
+            requirement,
+            gemfile_variant.gemfile(),
+            default,
+        );
+    }
+
+    match gemfile_lock.version_files.first() {
+        Some(version_file) => {
+            let requirement =
+                RubyRequirement::from_str(&version_file.requirement).map_err(|error| {
+                    RubyBuildpackError::RubyRequirementError(app_dir.join(version_file.file), error)
+                })?;
+            from_inventory(
+                inventory,
+                target,
+                requirement,
+                &gemfile_lock.ruby_source(),
+                default,
+            )
+        }
+        None => Ok(RubyChoice {
+            ruby: ResolvedRuby::mri(default),
+            source: RubySource::Default,
+            requirement: None,
+            warnings: Vec::new(),
+        }),
+    }
+}
+
+/// Sources that disagree with the chosen Ruby
+///
+/// - A `Gemfile` requirement that the chosen Ruby does not satisfy, bundler refuses to install
+/// - A `Gemfile.lock` version that the `Gemfile` requirement does not allow
+/// - Version files that the chosen Ruby does not satisfy
+///
+/// Sources that cannot be compared, such as a `.ruby-version` for another Ruby engine, are
+/// skipped.
+fn conflicts(
+    gemfile_variant: GemfileVariant,
+    gemfile_requirement: Option<&RubyRequirement>,
+    gemfile_lock: &GemfileLock,
+    choice: &RubyChoice,
+) -> Vec<VersionConflict> {
+    let Ok(chosen) = GemVersion::from_str(&choice.ruby.version.0) else {
+        return Vec::new();
+    };
+    let mut conflicts = Vec::new();
+    let against_choice = |file: &str, declared: &str| VersionConflict {
+        file: file.to_string(),
+        declared: declared.to_string(),
+        other_file: choice.source.to_string(),
+        other: choice.ruby.to_string(),
+    };
+
+    if let Some(requirement) = gemfile_requirement {
+        if !requirement.satisfied_by(&chosen) {
+            conflicts.push(against_choice(
+                gemfile_variant.gemfile(),
+                &requirement.to_string(),
+            ));
+        } else if let RubyVersion::Explicit { version, .. } = &gemfile_lock.ruby_version {
+            if GemVersion::from_str(version).is_ok_and(|locked| !requirement.satisfied_by(&locked))
+            {
+                conflicts.push(VersionConflict {
+                    file: gemfile_variant.lockfile().to_string(),
+                    declared: version.clone(),
+                    other_file: gemfile_variant.gemfile().to_string(),
+                    other: requirement.to_string(),
+                });
+            }
+        }
+    }
+
+    conflicts.extend(
+        gemfile_lock
+            .version_files
+            .iter()
+            .filter(|version_file| choice.source != RubySource::File(version_file.file.to_string()))
+            .filter(|version_file| {
+                RubyRequirement::from_str(&version_file.requirement)
+                    .is_ok_and(|requirement| !requirement.satisfied_by(&chosen))
+            })
+            .map(|version_file| against_choice(version_file.file, &version_file.requirement)),
+    );
+    conflicts
+}
+
+fn warning(conflict: &VersionConflict) -> String {
+    format!(
+        "Ruby version sources disagree. {file} declares Ruby {declared} but {other_file} \
+         declares Ruby {other}.\n\n\
+         Update {file} to match, or remove the version from it, so that the Ruby version used \
+         locally is the same as the one used in production. Set {strict} to fail the build \
+         instead of warning.",
+        file = style::value(&conflict.file),
+        declared = style::value(&conflict.declared),
+        other_file = style::value(&conflict.other_file),
+        other = style::value(&conflict.other),
+        strict = style::value(format!("{VERSION_CHECK_ENV_KEY}=strict")),
+    )
+}
+
+fn from_inventory(
+    inventory: &Inventory,
+    target: &TargetId,
+    requirement: RubyRequirement,
+    source: &str,
+    default: &str,
+) -> Result<RubyChoice, RubyBuildpackError> {
+    if !inventory.covers(target) {
+        let fallback = requirement.exact().map(ToString::to_string).or_else(|| {
+            GemVersion::from_str(default)
+                .is_ok_and(|version| requirement.satisfied_by(&version))
+                .then(|| default.to_string())
+        });
+        if let Some(version) = fallback {
+            return Ok(RubyChoice {
+                ruby: ResolvedRuby::mri(&version),
+                source: RubySource::File(source.to_string()),
+                requirement: Some(requirement),
+                warnings: Vec::new(),
+            });
+        }
+    }
+    let artifact = inventory.resolve(target, &requirement).ok_or_else(|| {
+        RubyBuildpackError::RubyInstallError(RubyInstallError::NoMatchingRuby {
+            requirement: requirement.to_string(),
+            file: source.to_string(),
+            target: Box::new(target.clone()),
+            suggestions: Box::new(inventory.suggest_for_requirement(target, &requirement)),
+        })
+    })?;
+
+    Ok(RubyChoice {
+        ruby: artifact.ruby(),
+        source: RubySource::File(source.to_string()),
+        requirement: Some(requirement),
+        warnings: Vec::new(),
+    })
+}
+
+fn read_optional(path: &Path) -> Result<Option<String>, io::Error> {
+    match fs_err::read_to_string(path) {
+        Ok(contents) => Ok(Some(contents)),
+        Err(error) if error.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(error) => Err(error),
+    }
+}
+
+/// Returns the version requirement from a `ruby` directive in the `Gemfile`
+///
+/// Only string literals are read i.e. `ruby "~> 3.3"` or `ruby ">= 3.1", "< 3.4"`. Directives
+/// that select an engine or read from a file, or that use any other Ruby expression, return `None`.
+fn gemfile_ruby_directive(gemfile: &str) -> Option<String> {
+    match Gemfile::parse(gemfile).ruby? {
+        RubyDirective::Version {
+            requirements,
+            engine: None,
+            ..
+        } => Some(requirements.join(", ")),
+        RubyDirective::Version { .. } | RubyDirective::File(_) => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ruby_inventory::fixtures::{inventory, target};
+    use commons::ruby_version_file::VersionFile;
+
+    #[test]
+    fn test_gemfile_ruby_directive() {
+        for (gemfile, expected) in [
+            ("ruby '3.3.6'", Some("3.3.6")),
+            ("  ruby \"~> 3.3\" # Patch releases", Some("~> 3.3")),
+            ("ruby(\"~> 3.3\")", Some("~> 3.3")),
+            ("ruby \">= 3.1\", \"< 3.4\"", Some(">= 3.1, < 3.4")),
+            ("ruby '3.3.6', patchlevel: '0'", Some("3.3.6")),
+            (
+                "ruby '2.6.8', engine: 'jruby', engine_version: '9.3.6.0'",
+                None,
+            ),
+            ("ruby file: '.ruby-version'", None),
+            ("ruby File.read('.ruby-version').strip", None),
+            ("ruby RUBY_VERSION", None),
+            ("gem 'ruby-progressbar'", None),
+            ("", None),
+        ] {
+            let gemfile = format!("source 'https://rubygems.org'\n\n{gemfile}\ngem 'rails'\n");
+            assert_eq!(
+                expected.map(String::from),
+                gemfile_ruby_directive(&gemfile),
+                "Parsing {gemfile:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_resolve_order() {
+        let app_dir = tempfile::tempdir().unwrap();
+        let inventory = inventory(&["3.2.6", "3.3.0", "3.3.6", "3.4.1"]);
+        let lockfile_explicit = "RUBY VERSION\n   ruby 3.3.0p0\n";
+        let lockfile_default = "";
+        let resolve = |lockfile: &str| {
+            resolve(
+                app_dir.path(),
+                GemfileVariant::Gemfile,
+                &GemfileLock::from_str(lockfile)
+                    .unwrap()
+                    .with_version_files(VersionFile::read_all(app_dir.path()).unwrap()),
+                &inventory,
+                &target(),
+                "3.1.3",
+                VersionCheck::Warn,
+            )
+            .unwrap()
+        };
+
+        let choice = resolve(lockfile_default);
+        assert_eq!(ResolvedRuby::mri("3.1.3"), choice.ruby);
+        assert_eq!(RubySource::Default, choice.source);
+
+        fs_err::write(app_dir.path().join(".tool-versions"), "ruby 3.4.1\n").unwrap();
+        let choice = resolve(lockfile_default);
+        assert_eq!(ResolvedRuby::mri("3.4.1"), choice.ruby);
+        assert_eq!(".tool-versions", choice.source.to_string());
+        assert!(choice.warnings.is_empty());
+
+        fs_err::write(app_dir.path().join(".ruby-version"), "3.2\n").unwrap();
+        let choice = resolve(lockfile_default);
+        assert_eq!(ResolvedRuby::mri("3.2.6"), choice.ruby);
+        assert_eq!(".ruby-version", choice.source.to_string());
+        assert_eq!(1, choice.warnings.len());
+        for expected in [
+            "Ruby version sources disagree",
+            ".tool-versions",
+            "3.4.1",
+            "3.2.6",
+        ] {
+            assert!(
+                choice.warnings[0].contains(expected),
+                "{:?}",
+                choice.warnings
+            );
+        }
+        fs_err::remove_file(app_dir.path().join(".tool-versions")).unwrap();
+
+        let choice = resolve(lockfile_explicit);
+        assert_eq!(ResolvedRuby::mri("3.3.0"), choice.ruby);
+        assert_eq!("Gemfile.lock", choice.source.to_string());
+        assert_eq!(1, choice.warnings.len());
+
+        fs_err::write(app_dir.path().join(".ruby-version"), "3.3\n").unwrap();
+        let choice = resolve(lockfile_explicit);
+        assert!(choice.warnings.is_empty());
+
+        fs_err::write(app_dir.path().join("Gemfile"), "ruby '3.3.0'\n").unwrap();
+        let choice = resolve(lockfile_explicit);
+        assert_eq!(ResolvedRuby::mri("3.3.0"), choice.ruby);
+        assert_eq!("Gemfile.lock", choice.source.to_string());
+
+        fs_err::write(app_dir.path().join("Gemfile"), "ruby '~> 3.3.0'\n").unwrap();
+        let choice = resolve(lockfile_explicit);
+        assert_eq!(ResolvedRuby::mri("3.3.0"), choice.ruby);
+        assert_eq!("Gemfile.lock", choice.source.to_string());
+        assert!(choice.warnings.is_empty());
+
+        let choice = resolve(lockfile_default);
+        assert_eq!(ResolvedRuby::mri("3.3.6"), choice.ruby);
+        assert_eq!("Gemfile", choice.source.to_string());
+        assert_eq!(
+            Some(String::from("~> 3.3.0")),
+            choice.requirement.map(|r| r.to_string())
+        );
+    }
+
+    #[test]
+    fn test_resolve_target_not_in_inventory() {
+        let app_dir = tempfile::tempdir().unwrap();
+        let resolve = |lockfile: &str| {
+            resolve(
+                app_dir.path(),
+                GemfileVariant::Gemfile,
+                &GemfileLock::from_str(lockfile)
+                    .unwrap()
+                    .with_version_files(VersionFile::read_all(app_dir.path()).unwrap()),
+                &Inventory::default(),
+                &target(),
+                "3.1.3",
+                VersionCheck::Warn,
+            )
+        };
+        let lockfile = "RUBY VERSION\n   ruby 3.3.6p108\n";
+
+        fs_err::write(app_dir.path().join("Gemfile"), "ruby '~> 3.3'\n").unwrap();
+        let choice = resolve(lockfile).unwrap();
+        assert_eq!(ResolvedRuby::mri("3.3.6"), choice.ruby);
+        assert_eq!("Gemfile.lock", choice.source.to_string());
+        assert!(choice.warnings.is_empty());
+        fs_err::remove_file(app_dir.path().join("Gemfile")).unwrap();
+
+        fs_err::write(app_dir.path().join(".ruby-version"), "3.3\n").unwrap();
+        let choice = resolve(lockfile).unwrap();
+        assert_eq!(ResolvedRuby::mri("3.3.6"), choice.ruby);
+        assert_eq!("Gemfile.lock", choice.source.to_string());
+        assert!(choice.warnings.is_empty());
+
+        fs_err::write(app_dir.path().join(".ruby-version"), "3.3.6\n").unwrap();
+        assert_eq!(ResolvedRuby::mri("3.3.6"), resolve("").unwrap().ruby);
+
+        fs_err::write(app_dir.path().join(".ruby-version"), "3.1\n").unwrap();
+        assert_eq!(ResolvedRuby::mri("3.1.3"), resolve("").unwrap().ruby);
+
+        fs_err::write(app_dir.path().join(".ruby-version"), "3.3\n").unwrap();
+        assert!(matches!(
+            resolve(""),
+            Err(RubyBuildpackError::RubyInstallError(
+                RubyInstallError::NoMatchingRuby { .. }
+            ))
+        ));
+    }
+
+    #[test]
+    fn test_version_conflicts() {
+        let app_dir = tempfile::tempdir().unwrap();
+        let inventory = inventory(&["3.2.6", "3.3.0", "3.3.6"]);
+        let resolve = |lockfile: &str, check: VersionCheck| {
+            resolve(
+                app_dir.path(),
+                GemfileVariant::Gemfile,
+                &GemfileLock::from_str(lockfile)
+                    .unwrap()
+                    .with_version_files(VersionFile::read_all(app_dir.path()).unwrap()),
+                &inventory,
+                &target(),
+                "3.1.3",
+                check,
+            )
+        };
+        let lockfile = "RUBY VERSION\n   ruby 3.2.6p0\n";
+
+        // Bundler would refuse to install with Ruby 3.2.6
+        fs_err::write(app_dir.path().join("Gemfile"), "ruby '3.3.6'\n").unwrap();
+        let choice = resolve(lockfile, VersionCheck::Warn).unwrap();
+        assert_eq!(ResolvedRuby::mri("3.2.6"), choice.ruby);
+        assert_eq!(1, choice.warnings.len());
+        for expected in ["Gemfile", "3.3.6", "Gemfile.lock", "3.2.6"] {
+            assert!(
+                choice.warnings[0].contains(expected),
+                "{:?}",
+                choice.warnings
+            );
+        }
+
+        // The lockfile version is outside of the Gemfile requirement
+        fs_err::write(app_dir.path().join("Gemfile"), "ruby '~> 3.3'\n").unwrap();
+        fs_err::write(app_dir.path().join(".ruby-version"), "3.3.6\n").unwrap();
+        let Err(RubyBuildpackError::RubyVersionCheckError(VersionCheckError::Conflicts(conflicts))) =
+            resolve(lockfile, VersionCheck::Strict)
+        else {
+            panic!("Expected a version conflict");
+        };
+        assert_eq!(
+            vec![
+                VersionConflict {
+                    file: String::from("Gemfile"),
+                    declared: String::from("~> 3.3"),
+                    other_file: String::from("Gemfile.lock"),
+                    other: String::from("3.2.6"),
+                },
+                VersionConflict {
+                    file: String::from(".ruby-version"),
+                    declared: String::from("3.3.6"),
+                    other_file: String::from("Gemfile.lock"),
+                    other: String::from("3.2.6"),
+                }
+            ],
+            conflicts
+        );
+
+        // Sources that agree pass the strict check
+        fs_err::write(app_dir.path().join(".ruby-version"), "3.3\n").unwrap();
+        let choice = resolve("RUBY VERSION\n   ruby 3.3.0p0\n", VersionCheck::Strict).unwrap();
+        assert_eq!(ResolvedRuby::mri("3.3.0"), choice.ruby);
+        assert!(choice.warnings.is_empty());
+    }
+
+    #[test]
+    fn test_version_check_from_config() {
+        let mut env = Env::new();
+        let mut config = ProjectConfig::default();
+        assert_eq!(
+            (VersionCheck::Warn, None),
+            VersionCheck::from_config(&env, &config).unwrap()
+        );
+
+        config.ruby_version_check = Some(VersionCheck::Strict);
+        assert_eq!(
+            (VersionCheck::Strict, Some(PROJECT_TOML)),
+            VersionCheck::from_config(&env, &config).unwrap()
+        );
+
+        env.insert(VERSION_CHECK_ENV_KEY, "Warn");
+        assert_eq!(
+            (VersionCheck::Warn, Some(VERSION_CHECK_ENV_KEY)),
+            VersionCheck::from_config(&env, &config).unwrap()
+        );
+
+        env.insert(VERSION_CHECK_ENV_KEY, "error");
+        assert!(matches!(
+            VersionCheck::from_config(&env, &config),
+            Err(VersionCheckError::InvalidSetting(_))
+        ));
+    }
+
+    #[test]
+    fn test_resolve_errors() {
+        let app_dir = tempfile::tempdir().unwrap();
+        let gemfile_lock = GemfileLock::from_str("").unwrap();
+        let resolve = || {
+            resolve(
+                app_dir.path(),
+                GemfileVariant::Gemfile,
+                &gemfile_lock,
+                &inventory(&["3.3.6"]),
+                &target(),
+                "3.1.3",
+                VersionCheck::Warn,
+            )
+        };
+
+        fs_err::write(app_dir.path().join("Gemfile"), "ruby '~> 2.7'\n").unwrap();
+        assert!(matches!(
+            resolve(),
+            Err(RubyBuildpackError::RubyInstallError(
+                RubyInstallError::NoMatchingRuby { .. }
+            ))
+        ));
+
+        fs_err::write(app_dir.path().join("Gemfile"), "ruby 'three'\n").unwrap();
+        assert!(matches!(
+            resolve(),
+            Err(RubyBuildpackError::RubyRequirementError(..))
+        ));
+    }
+}
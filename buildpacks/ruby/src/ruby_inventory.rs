@@ -0,0 +1,515 @@
+//! Ruby binaries available for install
+//!
+//! The inventory is a TOML file shipped with the buildpack that lists each Ruby
+//! per target with the URL to download it from and its sha256 checksum. Downloads
+//! are verified against the checksum before they're installed.
+//!
+//! Artifact URLs must follow the layout of `ruby_install_layer::download_url`.
+use crate::target_id::TargetId;
+use commons::gem_version::GemVersion;
+use commons::gemfile_lock::{ResolvedRuby, ResolvedRubyVersion, RubyEngine};
+use commons::ruby_requirement::RubyRequirement;
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::str::FromStr;
+
+const INVENTORY: &str = include_str!("../inventory.toml");
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq, Default)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct Inventory {
+    #[serde(default)]
+    pub(crate) artifacts: Vec<Artifact>,
+}
+
+/// A downloadable Ruby binary for a single target
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct Artifact {
+    pub(crate) version: ResolvedRubyVersion,
+    pub(crate) engine: RubyEngine,
+    pub(crate) engine_version: String,
+    pub(crate) distro_name: String,
+    pub(crate) distro_version: String,
+    pub(crate) arch: String,
+    pub(crate) url: String,
+    pub(crate) sha256: String,
+}
+
+impl Inventory {
+    /// Loads the inventory shipped with the buildpack
+    ///
+    /// # Errors
+    ///
+    /// Errors if the inventory file is not valid TOML or does not match the expected format.
+    pub(crate) fn shipped() -> Result<Self, toml::de::Error> {
+        toml::from_str(INVENTORY)
+    }
+
+    /// Returns the artifact for the given Ruby on the given target, if any
+    pub(crate) fn find(&self, target: &TargetId, ruby: &ResolvedRuby) -> Option<&Artifact> {
+        self.artifacts
+            .iter()
+            .find(|artifact| &artifact.target_id() == target && &artifact.ruby() == ruby)
+    }
+
+    /// True when the inventory lists any Ruby for the target
+    pub(crate) fn covers(&self, target: &TargetId) -> bool {
+        self.artifacts
+            .iter()
+            .any(|artifact| &artifact.target_id() == target)
+    }
+
+    /// Returns the newest MRI artifact for the target that satisfies the requirement
+    pub(crate) fn resolve(
+        &self,
+        target: &TargetId,
+        requirement: &RubyRequirement,
+    ) -> Option<&Artifact> {
+        requirement.newest(
+            self.artifacts.iter().filter(|artifact| {
+                artifact.engine == RubyEngine::Mri && &artifact.target_id() == target
+            }),
+            |artifact| GemVersion::from_str(&artifact.version.0).ok(),
+        )
+    }
+
+    /// Alternatives to a Ruby that is not available for the target
+    pub(crate) fn suggest(&self, target: &TargetId, ruby: &ResolvedRuby) -> Suggestions {
+        Suggestions {
+            closest: self.closest(target, ruby.engine, &version_key(ruby)),
+            other_targets: self.other_targets(target, |artifact| &artifact.ruby() == ruby),
+        }
+    }
+
+    /// Alternatives to a requirement that no Ruby for the target satisfies
+    pub(crate) fn suggest_for_requirement(
+        &self,
+        target: &TargetId,
+        requirement: &RubyRequirement,
+    ) -> Suggestions {
+        let requested = requirement.to_string();
+        let requested = requested
+            .split(',')
+            .next()
+            .unwrap_or_default()
+            .trim_start_matches(|c: char| !c.is_ascii_digit());
+
+        Suggestions {
+            closest: self.closest(target, RubyEngine::Mri, requested),
+            other_targets: self.other_targets(target, |artifact| {
+                self.resolve(&artifact.target_id(), requirement).is_some()
+            }),
+        }
+    }
+
+    /// Versions for the target that the requested version may have been meant as, ranked by:
+    ///
+    /// - The same minor series i.e. `3.3.6` for `3.3.9`
+    /// - A single character typo i.e. `3.2.6` for `3.3.6`
+    /// - Swapped segments i.e. `3.0.6` for `3.6.0`
+    fn closest(&self, target: &TargetId, engine: RubyEngine, requested: &str) -> Vec<ResolvedRuby> {
+        let segments = |version: &str| version.split('.').map(String::from).collect::<Vec<_>>();
+        let requested_segments = segments(requested);
+        let rank = |version: &str| {
+            let mut candidate = segments(version);
+            if candidate
+                .iter()
+                .take(2)
+                .eq(requested_segments.iter().take(2))
+            {
+                return Some(0);
+            }
+            if edit_distance(version, requested) == 1 {
+                return Some(1);
+            }
+            let mut requested = requested_segments.clone();
+            candidate.sort();
+            requested.sort();
+            (candidate == requested).then_some(2)
+        };
+
+        let mut candidates = self
+            .artifacts
+            .iter()
+            .filter(|artifact| artifact.engine == engine && &artifact.target_id() == target)
+            .filter_map(|artifact| {
+                let ruby = artifact.ruby();
+                let key = version_key(&ruby);
+                rank(&key).map(|rank| (rank, GemVersion::from_str(&key).unwrap_or_default(), ruby))
+            })
+            .collect::<Vec<_>>();
+
+        candidates.sort_by(|(rank_a, version_a, _), (rank_b, version_b, _)| {
+            rank_a
+                .cmp(rank_b)
+                .then(version_b.partial_cmp(version_a).unwrap_or(Ordering::Equal))
+        });
+        candidates
+            .into_iter()
+            .map(|(_, _, ruby)| ruby)
+            .take(MAX_SUGGESTIONS)
+            .collect()
+    }
+
+    fn other_targets(
+        &self,
+        target: &TargetId,
+        available: impl Fn(&Artifact) -> bool,
+    ) -> Vec<TargetId> {
+        let mut targets = Vec::new();
+        for artifact in self.artifacts.iter().filter(|artifact| available(artifact)) {
+            let other = artifact.target_id();
+            if &other != target && !targets.contains(&other) {
+                targets.push(other);
+            }
+        }
+        targets
+    }
+}
+
+const MAX_SUGGESTIONS: usize = 5;
+
+/// Alternatives when a requested Ruby is not available for a target
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub(crate) struct Suggestions {
+    /// Closest versions available for the target
+    pub(crate) closest: Vec<ResolvedRuby>,
+    /// Other targets where the requested Ruby is available
+    pub(crate) other_targets: Vec<TargetId>,
+}
+
+/// The version users write for an engine i.e. `3.3.6` for `ruby` and `9.4.8.0` for `jruby`
+fn version_key(ruby: &ResolvedRuby) -> String {
+    match ruby.engine {
+        RubyEngine::Mri => ruby.version.0.clone(),
+        _ => ruby.engine_version.clone(),
+    }
+}
+
+/// Levenshtein distance between two strings
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b = b.chars().collect::<Vec<_>>();
+    let mut previous = (0..=b.len()).collect::<Vec<_>>();
+    for (i, a_char) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, b_char) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(a_char != *b_char);
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+impl Artifact {
+    pub(crate) fn target_id(&self) -> TargetId {
+        TargetId {
+            distro_name: self.distro_name.clone(),
+            distro_version: self.distro_version.clone(),
+            cpu_architecture: self.arch.clone(),
+        }
+    }
+
+    pub(crate) fn ruby(&self) -> ResolvedRuby {
+        ResolvedRuby {
+            engine: self.engine,
+            engine_version: self.engine_version.clone(),
+            version: self.version.clone(),
+        }
+    }
+}
+
+/// Inventory fixtures for tests
+#[cfg(test)]
+pub(crate) mod fixtures {
+    use super::*;
+
+    /// Ubuntu 24.04 on amd64
+    pub(crate) fn target() -> TargetId {
+        TargetId {
+            distro_name: String::from("ubuntu"),
+            distro_version: String::from("24.04"),
+            cpu_architecture: String::from("amd64"),
+        }
+    }
+
+    /// An inventory with the given MRI versions for `target()`
+    pub(crate) fn inventory(versions: &[&str]) -> Inventory {
+        let target = target();
+        Inventory {
+            artifacts: versions
+                .iter()
+                .map(|&version| Artifact {
+                    version: ResolvedRubyVersion(version.to_string()),
+                    engine: RubyEngine::Mri,
+                    engine_version: version.to_string(),
+                    distro_name: target.distro_name.clone(),
+                    distro_version: target.distro_version.clone(),
+                    arch: target.cpu_architecture.clone(),
+                    url: String::new(),
+                    sha256: String::new(),
+                })
+                .collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::layers::ruby_install_layer::{download_url, BinaryMirror};
+    use crate::steps::DEFAULT_RUBY_VERSION;
+    use crate::target_id::all_target_ids;
+
+    fn target(distro_version: &str, arch: &str) -> TargetId {
+        TargetId {
+            distro_name: String::from("ubuntu"),
+            distro_version: String::from(distro_version),
+            cpu_architecture: String::from(arch),
+        }
+    }
+
+    #[test]
+    fn test_shipped_inventory_parses() {
+        let inventory = Inventory::shipped().unwrap();
+        for artifact in &inventory.artifacts {
+            assert_eq!(
+                64,
+                artifact.sha256.len(),
+                "Expected sha256 of {artifact:?} to be 64 hex characters"
+            );
+            assert_eq!(
+                artifact.url,
+                download_url(
+                    &BinaryMirror::default(),
+                    &artifact.target_id(),
+                    &artifact.ruby()
+                )
+                .unwrap()
+                .to_string(),
+                "Expected the URL of {artifact:?} to follow the download layout"
+            );
+        }
+    }
+
+    #[test]
+    #[ignore = "inventory.toml does not list checksummed artifacts yet"]
+    fn test_shipped_inventory_lists_default_ruby() {
+        let inventory = Inventory::shipped().unwrap();
+        let default = ResolvedRuby::mri(DEFAULT_RUBY_VERSION);
+        for target in all_target_ids() {
+            assert!(
+                inventory.find(&target, &default).is_some(),
+                "Expected {default} to be listed in inventory.toml for {target}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_covers() {
+        let inventory: Inventory = toml::from_str(
+            r#"
+[[artifacts]]
+version = "3.3.6"
+engine = "ruby"
+engine_version = "3.3.6"
+distro_name = "ubuntu"
+distro_version = "24.04"
+arch = "amd64"
+url = "https://example.com/heroku-24/amd64/ruby-3.3.6.tgz"
+sha256 = "ab"
+"#,
+        )
+        .unwrap();
+
+        assert!(inventory.covers(&target("24.04", "amd64")));
+        assert!(!inventory.covers(&target("24.04", "arm64")));
+    }
+
+    #[test]
+    fn test_find() {
+        let inventory: Inventory = toml::from_str(
+            r#"
+[[artifacts]]
+version = "3.3.6"
+engine = "ruby"
+engine_version = "3.3.6"
+distro_name = "ubuntu"
+distro_version = "24.04"
+arch = "arm64"
+url = "https://example.com/heroku-24/arm64/ruby-3.3.6.tgz"
+sha256 = "ab"
+
+[[artifacts]]
+version = "3.3.6"
+engine = "ruby"
+engine_version = "3.3.6"
+distro_name = "ubuntu"
+distro_version = "24.04"
+arch = "amd64"
+url = "https://example.com/heroku-24/amd64/ruby-3.3.6.tgz"
+sha256 = "cd"
+
+[[artifacts]]
+version = "3.1.4"
+engine = "jruby"
+engine_version = "9.4.8.0"
+distro_name = "ubuntu"
+distro_version = "22.04"
+arch = "amd64"
+url = "https://example.com/heroku-22/ruby-3.1.4-jruby-9.4.8.0.tgz"
+sha256 = "ef"
+"#,
+        )
+        .unwrap();
+
+        let artifact = inventory
+            .find(&target("24.04", "amd64"), &ResolvedRuby::mri("3.3.6"))
+            .unwrap();
+        assert_eq!("cd", artifact.sha256);
+
+        let jruby = ResolvedRuby {
+            engine: RubyEngine::Jruby,
+            engine_version: String::from("9.4.8.0"),
+            version: ResolvedRubyVersion(String::from("3.1.4")),
+        };
+        assert_eq!(
+            "ef",
+            inventory
+                .find(&target("22.04", "amd64"), &jruby)
+                .unwrap()
+                .sha256
+        );
+
+        assert_eq!(
+            None,
+            inventory.find(&target("22.04", "amd64"), &ResolvedRuby::mri("3.3.6"))
+        );
+        assert_eq!(
+            None,
+            inventory.find(&target("22.04", "amd64"), &ResolvedRuby::mri("3.1.4"))
+        );
+    }
+
+    #[test]
+    fn test_resolve() {
+        let artifact = |version: &str, distro_version: &str, arch: &str| Artifact {
+            version: ResolvedRubyVersion(version.to_string()),
+            engine: RubyEngine::Mri,
+            engine_version: version.to_string(),
+            distro_name: String::from("ubuntu"),
+            distro_version: distro_version.to_string(),
+            arch: arch.to_string(),
+            url: String::new(),
+            sha256: String::new(),
+        };
+        let inventory = Inventory {
+            artifacts: vec![
+                artifact("3.3.5", "24.04", "amd64"),
+                artifact("3.3.6", "24.04", "amd64"),
+                artifact("3.3.7", "24.04", "arm64"),
+                artifact("3.3.8", "22.04", "amd64"),
+                artifact("3.4.0.preview2", "24.04", "amd64"),
+            ],
+        };
+        let resolve = |requirement: &str| {
+            inventory
+                .resolve(
+                    &target("24.04", "amd64"),
+                    &RubyRequirement::from_str(requirement).unwrap(),
+                )
+                .map(|artifact| artifact.version.0.as_str())
+        };
+
+        assert_eq!(Some("3.3.6"), resolve("~> 3.3"));
+        assert_eq!(Some("3.3.6"), resolve("3.3"));
+        assert_eq!(Some("3.3.5"), resolve("3.3.5"));
+        assert_eq!(Some("3.4.0.preview2"), resolve("3.4.0.preview2"));
+        assert_eq!(None, resolve("3.3.8"));
+    }
+
+    #[test]
+    fn test_edit_distance() {
+        assert_eq!(0, edit_distance("3.3.6", "3.3.6"));
+        assert_eq!(1, edit_distance("3.3.6", "3.3.7"));
+        assert_eq!(2, edit_distance("3.6.0", "3.0.6"));
+        assert_eq!(
+            1,
+            edit_distance("3.3", "3.3.6").min(edit_distance("3.3.", "3.3.6"))
+        );
+        assert_eq!(5, edit_distance("", "3.3.6"));
+    }
+
+    #[test]
+    fn test_suggest() {
+        let artifact = |version: &str, distro_version: &str, arch: &str| Artifact {
+            version: ResolvedRubyVersion(version.to_string()),
+            engine: RubyEngine::Mri,
+            engine_version: version.to_string(),
+            distro_name: String::from("ubuntu"),
+            distro_version: distro_version.to_string(),
+            arch: arch.to_string(),
+            url: String::new(),
+            sha256: String::new(),
+        };
+        let inventory = Inventory {
+            artifacts: vec![
+                artifact("3.0.6", "22.04", "amd64"),
+                artifact("3.0.7", "22.04", "amd64"),
+                artifact("3.3.5", "22.04", "amd64"),
+                artifact("3.3.6", "22.04", "amd64"),
+                artifact("3.4.1", "22.04", "amd64"),
+                artifact("3.0.6", "24.04", "amd64"),
+                artifact("3.3.7", "24.04", "amd64"),
+                artifact("3.3.7", "24.04", "arm64"),
+            ],
+        };
+        let versions = |suggestions: &Suggestions| {
+            suggestions
+                .closest
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>()
+        };
+
+        // Typo of 3.0.6
+        let suggestions = inventory.suggest(&target("22.04", "amd64"), &ResolvedRuby::mri("3.6.0"));
+        assert_eq!(vec!["3.0.6"], versions(&suggestions));
+        assert!(suggestions.other_targets.is_empty());
+
+        // Patch release that only exists on newer distros
+        let suggestions = inventory.suggest(&target("22.04", "amd64"), &ResolvedRuby::mri("3.3.7"));
+        assert_eq!(vec!["3.3.6", "3.3.5", "3.0.7"], versions(&suggestions));
+        assert_eq!(
+            vec![target("24.04", "amd64"), target("24.04", "arm64")],
+            suggestions.other_targets
+        );
+
+        let suggestions = inventory.suggest_for_requirement(
+            &target("24.04", "amd64"),
+            &RubyRequirement::from_str("~> 3.0.7").unwrap(),
+        );
+        assert_eq!(vec!["3.0.6", "3.3.7"], versions(&suggestions));
+        assert_eq!(vec![target("22.04", "amd64")], suggestions.other_targets);
+    }
+
+    #[test]
+    fn test_unknown_fields_are_rejected() {
+        assert!(toml::from_str::<Inventory>(
+            r#"
+[[artifacts]]
+version = "3.3.6"
+engine = "ruby"
+engine_version = "3.3.6"
+distro_name = "ubuntu"
+distro_version = "24.04"
+arch = "arm64"
+url = "https://example.com/heroku-24/arm64/ruby-3.3.6.tgz"
+sha256 = "ab"
+checksum = "ab"
+"#,
+        )
+        .is_err());
+    }
+}
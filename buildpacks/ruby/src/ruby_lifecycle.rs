@@ -0,0 +1,332 @@
+//! End-of-life and security status of Ruby versions
+//!
+//! The status of each release series is read from `ruby_lifecycle.toml`, which ships with the
+//! buildpack so the check works offline. Series are listed per engine and looked up by the
+//! engine version, alternative engines follow their own release schedule and are never matched
+//! against MRI series. Engines without data are not checked.
+use chrono::NaiveDate;
+use commons::gem_version::GemVersion;
+use commons::gemfile_lock::{ResolvedRuby, RubyEngine};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fmt::Display;
+use std::str::FromStr;
+
+const LIFECYCLE: &str = include_str!("../ruby_lifecycle.toml");
+
+/// Warn this many days ahead of the end-of-life date of a series
+const EOL_SOON_DAYS: i64 = 90;
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct Lifecycle {
+    /// MRI series
+    #[serde(default)]
+    ruby: BTreeMap<String, Series>,
+    #[serde(default)]
+    jruby: BTreeMap<String, Series>,
+    #[serde(default)]
+    truffleruby: BTreeMap<String, Series>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Series {
+    eol: toml::value::Datetime,
+    /// Engine versions i.e. `9.4.8.0` for `jruby`
+    #[serde(default)]
+    security_releases: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum LifecycleWarning {
+    EndOfLife {
+        ruby: ResolvedRuby,
+        series: String,
+        eol: NaiveDate,
+    },
+    EndOfLifeSoon {
+        ruby: ResolvedRuby,
+        series: String,
+        eol: NaiveDate,
+    },
+    SecurityRelease {
+        ruby: ResolvedRuby,
+        series: String,
+        releases: Vec<String>,
+    },
+}
+
+impl Display for LifecycleWarning {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LifecycleWarning::EndOfLife { ruby, series, eol } => write!(
+                f,
+                "Ruby {ruby} is past its end-of-life. The {engine} {series} series stopped \
+                 receiving security updates on {eol}. Upgrade to a supported Ruby version.",
+                engine = engine_name(ruby.engine),
+            ),
+            LifecycleWarning::EndOfLifeSoon { ruby, series, eol } => write!(
+                f,
+                "Ruby {ruby} is close to its end-of-life. The {engine} {series} series stops \
+                 receiving security updates on {eol}. Plan an upgrade to a newer Ruby version.",
+                engine = engine_name(ruby.engine),
+            ),
+            LifecycleWarning::SecurityRelease {
+                ruby,
+                series,
+                releases,
+            } => write!(
+                f,
+                "Ruby {ruby} has known security vulnerabilities that are fixed in {releases}. \
+                 Upgrade to the latest {engine} {series}.x release.",
+                releases = releases.join(", "),
+                engine = engine_name(ruby.engine),
+            ),
+        }
+    }
+}
+
+impl Lifecycle {
+    /// The lifecycle data shipped with the buildpack
+    pub(crate) fn shipped() -> Result<Self, toml::de::Error> {
+        toml::from_str(LIFECYCLE)
+    }
+
+    /// The release series of a Ruby and its lifecycle, i.e. `3.3` for `3.3.6` or `9.4` for
+    /// `3.1.4 (jruby 9.4.8.0)`, along with the parsed engine version
+    ///
+    /// End-of-life dates are announced per series and each security release belongs to one, so
+    /// the status of every version follows from its series without listing each version.
+    fn series(&self, ruby: &ResolvedRuby) -> Option<(String, &Series, GemVersion)> {
+        let engine_series = match ruby.engine {
+            RubyEngine::Mri => &self.ruby,
+            RubyEngine::Jruby => &self.jruby,
+            RubyEngine::Truffleruby => &self.truffleruby,
+        };
+        let version = GemVersion::from_str(&ruby.engine_version).ok()?;
+        let name = version.series();
+        engine_series
+            .get(&name)
+            .map(|series| (name, series, version))
+    }
+
+    pub(crate) fn warnings(&self, ruby: &ResolvedRuby, today: NaiveDate) -> Vec<LifecycleWarning> {
+        let Some((series_name, series, current)) = self.series(ruby) else {
+            return Vec::new();
+        };
+
+        let mut warnings = Vec::new();
+        if let Some(eol) = date_from_toml(&series.eol) {
+            if today >= eol {
+                warnings.push(LifecycleWarning::EndOfLife {
+                    ruby: ruby.clone(),
+                    series: series_name.clone(),
+                    eol,
+                });
+            } else if (eol - today).num_days() <= EOL_SOON_DAYS {
+                warnings.push(LifecycleWarning::EndOfLifeSoon {
+                    ruby: ruby.clone(),
+                    series: series_name.clone(),
+                    eol,
+                });
+            }
+        }
+
+        let releases = series
+            .security_releases
+            .iter()
+            .filter(|release| GemVersion::from_str(release).is_ok_and(|release| release > current))
+            .cloned()
+            .collect::<Vec<_>>();
+        if !releases.is_empty() {
+            warnings.push(LifecycleWarning::SecurityRelease {
+                ruby: ruby.clone(),
+                series: series_name,
+                releases,
+            });
+        }
+        warnings
+    }
+}
+
+fn engine_name(engine: RubyEngine) -> &'static str {
+    match engine {
+        RubyEngine::Mri => "Ruby",
+        RubyEngine::Jruby => "JRuby",
+        RubyEngine::Truffleruby => "TruffleRuby",
+    }
+}
+
+/// The current date in UTC
+pub(crate) fn today() -> NaiveDate {
+    chrono::Utc::now().date_naive()
+}
+
+/// The date of a TOML date or datetime, `None` for a time without a date
+pub(crate) fn date_from_toml(datetime: &toml::value::Datetime) -> Option<NaiveDate> {
+    datetime.date.and_then(|date| {
+        NaiveDate::from_ymd_opt(
+            i32::from(date.year),
+            u32::from(date.month),
+            u32::from(date.day),
+        )
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use commons::gemfile_lock::ResolvedRubyVersion;
+
+    #[test]
+    fn test_shipped_lifecycle_parses() {
+        let lifecycle = Lifecycle::shipped().unwrap();
+        assert!(!lifecycle.ruby.is_empty());
+        for (name, series) in lifecycle
+            .ruby
+            .iter()
+            .chain(&lifecycle.jruby)
+            .chain(&lifecycle.truffleruby)
+        {
+            assert!(date_from_toml(&series.eol).is_some(), "{name} eol");
+            for release in &series.security_releases {
+                assert_eq!(
+                    name,
+                    &GemVersion::from_str(release).unwrap().series(),
+                    "{release} is not in the {name} series"
+                );
+            }
+        }
+    }
+
+    fn date(year: i32, month: u32, day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(year, month, day).unwrap()
+    }
+
+    #[test]
+    fn test_date_from_toml() {
+        let value: toml::Value = toml::from_str("eol = 2024-02-29").unwrap();
+        assert_eq!(
+            Some(date(2024, 2, 29)),
+            value["eol"].as_datetime().and_then(date_from_toml)
+        );
+        assert_eq!("2024-02-29", date(2024, 2, 29).to_string());
+        assert!(today() > date(2024, 1, 1));
+    }
+
+    #[test]
+    fn test_series() {
+        let lifecycle = Lifecycle::shipped().unwrap();
+        let series = |version: &str| {
+            lifecycle
+                .series(&ResolvedRuby::mri(version))
+                .map(|(name, _, _)| name)
+        };
+        assert_eq!(Some(String::from("3.3")), series("3.3.6"));
+        assert_eq!(Some(String::from("3.4")), series("3.4.0.preview2"));
+        assert_eq!(Some(String::from("3.0")), series("3.0.0"));
+        assert_eq!(None, series("1.9.3"));
+    }
+
+    #[test]
+    fn test_warnings() {
+        let lifecycle: Lifecycle = toml::from_str(
+            r#"
+            [ruby."3.1"]
+            eol = 2025-03-26
+            security_releases = ["3.1.4", "3.1.5"]
+            "#,
+        )
+        .unwrap();
+        let warnings = |version: &str, today: NaiveDate| {
+            lifecycle
+                .warnings(&ResolvedRuby::mri(version), today)
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>()
+        };
+
+        assert!(warnings("3.1.5", date(2024, 1, 1)).is_empty());
+        assert!(warnings("3.3.0", date(2030, 1, 1)).is_empty());
+
+        assert_eq!(
+            vec![String::from(
+                "Ruby 3.1.4 has known security vulnerabilities that are fixed in 3.1.5. \
+                 Upgrade to the latest Ruby 3.1.x release."
+            )],
+            warnings("3.1.4", date(2024, 1, 1))
+        );
+
+        let soon = warnings("3.1.5", date(2025, 2, 1));
+        assert_eq!(1, soon.len());
+        assert!(soon[0].contains("close to its end-of-life"), "{soon:?}");
+        assert!(soon[0].contains("2025-03-26"), "{soon:?}");
+
+        let eol = warnings("3.1.3", date(2025, 3, 26));
+        assert_eq!(2, eol.len());
+        assert!(eol[0].contains("past its end-of-life"), "{eol:?}");
+        assert!(eol[1].contains("3.1.4, 3.1.5"), "{eol:?}");
+    }
+
+    #[test]
+    fn test_jruby_warnings() {
+        let lifecycle: Lifecycle = toml::from_str(
+            r#"
+            [ruby."3.1"]
+            eol = 2025-03-26
+            security_releases = ["3.1.4", "3.1.5"]
+
+            [jruby."9.4"]
+            eol = 2026-01-01
+            security_releases = ["9.4.9.0"]
+            "#,
+        )
+        .unwrap();
+        let jruby = |ruby_version: &str, engine_version: &str| ResolvedRuby {
+            engine: RubyEngine::Jruby,
+            engine_version: engine_version.to_string(),
+            version: ResolvedRubyVersion(ruby_version.to_string()),
+        };
+
+        // The MRI compatible version is in the MRI 3.1 series, but JRuby 9.4 is supported
+        let ruby = jruby("3.1.3", "9.4.9.0");
+        assert!(lifecycle.warnings(&ruby, date(2025, 6, 1)).is_empty());
+
+        let ruby = jruby("3.1.3", "9.4.8.0");
+        assert_eq!(
+            vec![LifecycleWarning::SecurityRelease {
+                ruby: ruby.clone(),
+                series: String::from("9.4"),
+                releases: vec![String::from("9.4.9.0")],
+            }],
+            lifecycle.warnings(&ruby, date(2025, 6, 1))
+        );
+        assert_eq!(
+            "Ruby 3.1.3 (jruby 9.4.8.0) has known security vulnerabilities that are fixed in \
+             9.4.9.0. Upgrade to the latest JRuby 9.4.x release.",
+            lifecycle.warnings(&ruby, date(2025, 6, 1))[0].to_string()
+        );
+
+        let eol = lifecycle.warnings(&jruby("3.1.3", "9.4.9.0"), date(2026, 1, 1));
+        assert_eq!(1, eol.len());
+        assert!(
+            eol[0].to_string().contains("The JRuby 9.4 series stopped"),
+            "{eol:?}"
+        );
+
+        // No data for the JRuby 9.3 series
+        assert!(lifecycle
+            .warnings(&jruby("2.6.8", "9.3.6.0"), date(2030, 1, 1))
+            .is_empty());
+        // TruffleRuby versions are not matched against the JRuby or MRI series
+        let truffleruby = ResolvedRuby {
+            engine: RubyEngine::Truffleruby,
+            engine_version: String::from("9.4.8.0"),
+            version: ResolvedRubyVersion(String::from("3.1.3")),
+        };
+        assert!(lifecycle
+            .warnings(&truffleruby, date(2030, 1, 1))
+            .is_empty());
+    }
+}
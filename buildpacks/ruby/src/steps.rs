@@ -1,9 +1,13 @@
 mod default_env;
+mod default_ruby;
 mod detect_rake_tasks;
 mod get_default_process;
 mod rake_assets_install;
 
-pub(crate) use self::default_env::default_env;
+pub(crate) use self::default_env::{default_env, versioned_env_defaults};
+pub(crate) use self::default_ruby::{
+    record_default_ruby, sticky_default_ruby, DEFAULT_RUBY_VERSION,
+};
 pub(crate) use self::detect_rake_tasks::detect_rake_tasks;
 pub(crate) use self::get_default_process::get_default_process;
 pub(crate) use self::rake_assets_install::rake_assets_install;
@@ -1,4 +1,8 @@
+use crate::gem_credentials::without_credentials;
 use crate::{RubyBuildpack, RubyBuildpackError};
+use bullet_stream::{state::SubBullet, style, Print};
+use commons::gem_version::GemVersion;
+use commons::gemfile_lock::{ResolvedRuby, RubyEngine};
 use libcnb::layer::UncachedLayerDefinition;
 use libcnb::layer_env::{LayerEnv, ModificationBehavior};
 use libcnb::{
@@ -8,6 +12,8 @@ use libcnb::{
     Env,
 };
 use rand::Rng;
+use std::io::Stdout;
+use std::str::FromStr;
 
 // Set default environment values
 pub(crate) fn default_env(
@@ -22,6 +28,8 @@ pub(crate) fn default_env(
     for (k, v) in platform_env {
         env.insert(k, v);
     }
+    // Gem source credentials are only given to `bundle install`
+    let env = without_credentials(&env);
 
     let (default_secret_key_base, store) = fetch_secret_key_base_from_store(&context.store);
     let layer_ref = context.uncached_layer(
@@ -54,6 +62,101 @@ pub(crate) fn default_env(
     Ok((env, store))
 }
 
+/// An environment variable default that only applies to some Ruby versions
+struct VersionedDefault {
+    name: &'static str,
+    value: &'static str,
+    engine: RubyEngine,
+    /// Lowest version of the engine the default applies to
+    minimum: &'static str,
+    /// Why the default is set, shown in the build output
+    reason: &'static str,
+}
+
+const VERSIONED_DEFAULTS: &[VersionedDefault] = &[VersionedDefault {
+    name: "RUBY_YJIT_ENABLE",
+    value: "1",
+    engine: RubyEngine::Mri,
+    minimum: "3.3.0",
+    reason:
+        "the YJIT just-in-time compiler speeds up most applications with little memory overhead",
+}];
+
+impl VersionedDefault {
+    fn applies_to(&self, ruby: &ResolvedRuby) -> bool {
+        let version = match ruby.engine {
+            RubyEngine::Mri => &ruby.version.0,
+            RubyEngine::Jruby | RubyEngine::Truffleruby => &ruby.engine_version,
+        };
+        ruby.engine == self.engine
+            && GemVersion::from_str(version)
+                .ok()
+                .zip(GemVersion::from_str(self.minimum).ok())
+                .is_some_and(|(version, minimum)| version >= minimum)
+    }
+
+    fn requirement(&self) -> String {
+        format!("{} {}+", self.engine, self.minimum)
+    }
+}
+
+/// Set environment defaults that depend on the Ruby version
+///
+/// Defaults use `ModificationBehavior::Default` so a value set by the user, at build or at
+/// launch, takes precedence. Each default is listed in the build output with the reason it was
+/// set, or why it was skipped.
+pub(crate) fn versioned_env_defaults(
+    context: &BuildContext<RubyBuildpack>,
+    mut bullet: Print<SubBullet<Stdout>>,
+    ruby: &ResolvedRuby,
+    env: &Env,
+) -> libcnb::Result<(Print<SubBullet<Stdout>>, Env), RubyBuildpackError> {
+    let mut layer_env = LayerEnv::new();
+    for default in VERSIONED_DEFAULTS {
+        let name = default.name;
+        let requirement = default.requirement();
+        if !default.applies_to(ruby) {
+            bullet = bullet.sub_bullet(format!(
+                "Skipping {} (requires {requirement})",
+                style::value(name)
+            ));
+            continue;
+        }
+
+        layer_env = layer_env.chainable_insert(
+            Scope::All,
+            ModificationBehavior::Default,
+            name,
+            default.value,
+        );
+        bullet = bullet.sub_bullet(match env.get_string_lossy(name) {
+            Some(value) => format!(
+                "Using {} from the environment instead of the default {} for {requirement}",
+                style::value(format!("{name}={value}")),
+                style::value(default.value)
+            ),
+            None => format!(
+                "Setting {} for {requirement}, {reason}. Set {} to override",
+                style::value(format!("{name}={}", default.value)),
+                style::value(name),
+                reason = default.reason
+            ),
+        });
+    }
+
+    let layer_ref = context.uncached_layer(
+        layer_name!("ruby_env_defaults"),
+        UncachedLayerDefinition {
+            build: true,
+            launch: true,
+        },
+    )?;
+    layer_ref.write_env(layer_env)?;
+    let env = layer_ref.read_env()?.apply(Scope::Build, env);
+
+    Ok((bullet, env))
+}
+
 fn fetch_secret_key_base_from_store(store: &Option<Store>) -> (String, Store) {
     let mut store = store.clone().unwrap_or_default();
     let default_secret_key_base = store
@@ -71,3 +174,46 @@ fn fetch_secret_key_base_from_store(store: &Option<Store>) -> (String, Store) {
 
     (default_secret_key_base, store)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::layers::temp_build_context;
+    use commons::gemfile_lock::ResolvedRubyVersion;
+
+    fn yjit() -> &'static VersionedDefault {
+        VERSIONED_DEFAULTS
+            .iter()
+            .find(|default| default.name == "RUBY_YJIT_ENABLE")
+            .unwrap()
+    }
+
+    #[test]
+    fn test_versioned_default_applies_to() {
+        assert!(yjit().applies_to(&ResolvedRuby::mri("3.3.0")));
+        assert!(yjit().applies_to(&ResolvedRuby::mri("3.4.1")));
+        assert!(!yjit().applies_to(&ResolvedRuby::mri("3.2.6")));
+        assert!(!yjit().applies_to(&ResolvedRuby {
+            engine: RubyEngine::Jruby,
+            engine_version: String::from("9.4.9.0"),
+            version: ResolvedRubyVersion(String::from("3.3.0")),
+        }));
+        assert_eq!("ruby 3.3.0+", yjit().requirement());
+    }
+
+    #[test]
+    fn test_default_env_excludes_gem_credentials() {
+        let temp = tempfile::tempdir().unwrap();
+        let context = temp_build_context::<RubyBuildpack>(temp.path());
+        let mut platform_env = Env::new();
+        platform_env.insert("BUNDLE_GEMS__EXAMPLE__COM", "deploy:s3cr3t-token");
+        platform_env.insert("BUNDLE_WITHOUT", "development:test");
+
+        let (env, _) = default_env(&context, &platform_env).unwrap();
+        assert!(!env.contains_key("BUNDLE_GEMS__EXAMPLE__COM"));
+        assert_eq!(
+            Some(String::from("development:test")),
+            env.get_string_lossy("BUNDLE_WITHOUT")
+        );
+    }
+}
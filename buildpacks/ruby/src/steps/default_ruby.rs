@@ -0,0 +1,137 @@
+use crate::ruby_inventory::Inventory;
+use crate::target_id::TargetId;
+use commons::gem_version::GemVersion;
+use commons::gemfile_lock::{ResolvedRuby, ResolvedRubyVersion};
+use libcnb::data::store::Store;
+use std::str::FromStr;
+
+/// Ruby version installed for applications that do not specify one
+pub(crate) const DEFAULT_RUBY_VERSION: &str = "3.1.3";
+
+/// Key in the buildpack `Store` metadata recording the default Ruby an application was built with
+const STORE_KEY: &str = "DEFAULT_RUBY_VERSION";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct DefaultRuby {
+    /// Default Ruby version to use for this build
+    pub(crate) version: String,
+    /// Set when the buildpack default is newer than the version recorded by a previous build
+    pub(crate) newer: Option<String>,
+}
+
+/// Returns the default Ruby version recorded by a previous build, or the buildpack default
+///
+/// Changing the buildpack default would otherwise upgrade the Ruby of every application that
+/// does not specify one on its next deploy. A recorded version that is no longer available for
+/// the target is ignored.
+pub(crate) fn sticky_default_ruby(
+    store: Option<&Store>,
+    inventory: &Inventory,
+    target: &TargetId,
+    current: &str,
+) -> DefaultRuby {
+    let recorded = store
+        .and_then(|store| store.metadata.get(STORE_KEY))
+        .and_then(toml::Value::as_str)
+        .filter(|version| {
+            inventory
+                .find(target, &ResolvedRuby::mri(version))
+                .is_some()
+        });
+
+    match recorded {
+        Some(version) => DefaultRuby {
+            version: version.to_string(),
+            newer: GemVersion::from_str(current)
+                .ok()
+                .zip(GemVersion::from_str(version).ok())
+                .filter(|(current, recorded)| current > recorded)
+                .map(|_| current.to_string()),
+        },
+        None => DefaultRuby {
+            version: current.to_string(),
+            newer: None,
+        },
+    }
+}
+
+/// Records the default Ruby version used by this build, or clears it when the application
+/// specified a version
+pub(crate) fn record_default_ruby(store: &mut Store, version: Option<&ResolvedRubyVersion>) {
+    match version {
+        Some(version) => {
+            store
+                .metadata
+                .insert(STORE_KEY.to_string(), version.0.clone().into());
+        }
+        None => {
+            store.metadata.remove(STORE_KEY);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ruby_inventory::fixtures::{inventory, target};
+
+    #[test]
+    fn test_sticky_default_ruby() {
+        let inventory = inventory(&["3.1.3", "3.3.6"]);
+        let target = target();
+
+        assert_eq!(
+            DefaultRuby {
+                version: String::from("3.3.6"),
+                newer: None,
+            },
+            sticky_default_ruby(None, &inventory, &target, "3.3.6")
+        );
+
+        let mut store = Store::default();
+        record_default_ruby(
+            &mut store,
+            Some(&ResolvedRubyVersion(String::from("3.1.3"))),
+        );
+        let store = Some(&store);
+        assert_eq!(
+            DefaultRuby {
+                version: String::from("3.1.3"),
+                newer: Some(String::from("3.3.6")),
+            },
+            sticky_default_ruby(store, &inventory, &target, "3.3.6")
+        );
+        assert_eq!(
+            DefaultRuby {
+                version: String::from("3.1.3"),
+                newer: None,
+            },
+            sticky_default_ruby(store, &inventory, &target, "3.1.3")
+        );
+
+        // Recorded version is not available for this target
+        assert_eq!(
+            DefaultRuby {
+                version: String::from("3.3.6"),
+                newer: None,
+            },
+            sticky_default_ruby(store, &self::inventory(&["3.3.6"]), &target, "3.3.6")
+        );
+    }
+
+    #[test]
+    fn test_record_default_ruby() {
+        let mut store = Store::default();
+        record_default_ruby(
+            &mut store,
+            Some(&ResolvedRubyVersion(String::from("3.1.3"))),
+        );
+        assert_eq!(
+            Some("3.1.3"),
+            store.metadata.get(STORE_KEY).and_then(toml::Value::as_str)
+        );
+
+        record_default_ruby(&mut store, None);
+        assert_eq!(None, store.metadata.get(STORE_KEY));
+    }
+}
@@ -1,65 +1,274 @@
+use crate::ruby_lifecycle::date_from_toml;
+use chrono::NaiveDate;
+use serde::Deserialize;
+use std::sync::LazyLock;
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub(crate) struct TargetId {
     pub(crate) distro_name: String,
     pub(crate) distro_version: String,
     pub(crate) cpu_architecture: String,
 }
-const ARCH_AWARE_VERSIONS: &[&str] = &["24.04"];
-const DISTRO_VERSION_STACK: &[(&str, &str, &str)] = &[
-    ("ubuntu", "20.04", "heroku-20"),
-    ("ubuntu", "22.04", "heroku-22"),
-    ("ubuntu", "24.04", "heroku-24"),
-];
+
+static TARGETS: LazyLock<Vec<Target>> = LazyLock::new(|| {
+    toml::from_str::<Targets>(include_str!("../targets.toml"))
+        .expect("Internal error: Bad targets.toml") // Checked via test
+        .targets
+});
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Targets {
+    targets: Vec<Target>,
+}
+
+/// A distribution, version, and CPU architecture that Ruby binaries are built for
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct Target {
+    pub(crate) distro_name: String,
+    pub(crate) distro_version: String,
+    pub(crate) arch: String,
+    pub(crate) stack: String,
+    pub(crate) url_layout: UrlLayout,
+    #[serde(default)]
+    pub(crate) deprecated: bool,
+    pub(crate) sunset: Option<toml::value::Datetime>,
+}
+
+/// Where binaries for a target are stored relative to the binary mirror
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum UrlLayout {
+    /// `<mirror>/<stack>/<file>`
+    Stack,
+    /// `<mirror>/<stack>/<arch>/<file>`
+    StackArch,
+}
+
+fn known_distros() -> String {
+    let mut distros = TARGETS
+        .iter()
+        .map(|target| format!("'{}-{}'", target.distro_name, target.distro_version))
+        .collect::<Vec<_>>();
+    distros.dedup();
+    distros.join(", ")
+}
+
+fn known_architectures(distro_name: &str, distro_version: &str) -> String {
+    TARGETS
+        .iter()
+        .filter(|target| {
+            target.distro_name == distro_name && target.distro_version == distro_version
+        })
+        .map(|target| format!("'{}'", target.arch))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn known_stacks() -> String {
+    let mut stacks = TARGETS
+        .iter()
+        .map(|target| format!("'{}'", target.stack))
+        .collect::<Vec<_>>();
+    stacks.dedup();
+    stacks.join(", ")
+}
 
 #[derive(Debug, thiserror::Error)]
 pub(crate) enum TargetIdError {
-    #[error("Distro name and version '{0}-{1}' is not supported. Must be one of: {}", DISTRO_VERSION_STACK.iter().map(|&(name, version, _)| format!("'{name}-{version}'")).collect::<Vec<_>>().join(", "))]
+    #[error(
+        "Distro name and version '{0}-{1}' is not supported. Must be one of: {}",
+        known_distros()
+    )]
     UnknownDistroNameVersionCombo(String, String),
 
-    #[error("Cannot convert stack name '{0}' into a target OS. Must be one of: {}", DISTRO_VERSION_STACK.iter().map(|&(_, _, stack)| format!("'{stack}'")).collect::<Vec<_>>().join(", "))]
+    #[error("CPU architecture '{2}' is not supported on '{0}-{1}'. Must be one of: {}", known_architectures(.0, .1))]
+    UnsupportedArchitecture(String, String, String),
+
+    #[error(
+        "Cannot convert stack name '{0}' into a target OS. Must be one of: {}",
+        known_stacks()
+    )]
     UnknownStack(String),
 }
 
-impl TargetId {
-    pub(crate) fn is_arch_aware(&self) -> bool {
-        ARCH_AWARE_VERSIONS.contains(&self.distro_version.as_str())
+impl std::fmt::Display for TargetId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} {} ({})",
+            self.distro_name, self.distro_version, self.cpu_architecture
+        )
     }
+}
 
-    pub(crate) fn stack_name(&self) -> Result<String, TargetIdError> {
-        DISTRO_VERSION_STACK
-            .iter()
-            .find(|&&(name, version, _)| name == self.distro_name && version == self.distro_version)
-            .map(|&(_, _, stack)| stack.to_owned())
+impl TargetId {
+    /// The entry for this distribution, version, and CPU architecture in the target table
+    pub(crate) fn target(&self) -> Result<&'static Target, TargetIdError> {
+        let mut distro = TARGETS.iter().filter(|target| {
+            target.distro_name == self.distro_name && target.distro_version == self.distro_version
+        });
+        if distro.clone().next().is_none() {
+            return Err(TargetIdError::UnknownDistroNameVersionCombo(
+                self.distro_name.clone(),
+                self.distro_version.clone(),
+            ));
+        }
+        distro
+            .find(|target| target.arch == self.cpu_architecture)
             .ok_or_else(|| {
-                TargetIdError::UnknownDistroNameVersionCombo(
+                TargetIdError::UnsupportedArchitecture(
                     self.distro_name.clone(),
                     self.distro_version.clone(),
+                    self.cpu_architecture.clone(),
                 )
             })
     }
 
+    /// Stacks predate multi-architecture support, they always refer to `amd64`
     pub(crate) fn from_stack(stack_id: &str) -> Result<Self, TargetIdError> {
-        DISTRO_VERSION_STACK
+        TARGETS
             .iter()
-            .find(|&&(_, _, stack)| stack == stack_id)
-            .map(|&(name, version, _)| TargetId {
-                cpu_architecture: String::from("amd64"),
-                distro_name: name.to_owned(),
-                distro_version: version.to_owned(),
+            .find(|target| target.stack == stack_id && target.arch == "amd64")
+            .map(|target| TargetId {
+                cpu_architecture: target.arch.clone(),
+                distro_name: target.distro_name.clone(),
+                distro_version: target.distro_version.clone(),
             })
             .ok_or_else(|| TargetIdError::UnknownStack(stack_id.to_owned()))
     }
 }
 
+impl Target {
+    /// A warning for deprecated targets, stating the sunset date when there is one
+    pub(crate) fn deprecation_warning(&self, today: NaiveDate) -> Option<String> {
+        if !self.deprecated {
+            return None;
+        }
+        let Target {
+            stack,
+            distro_name,
+            distro_version,
+            ..
+        } = self;
+        let status = match self.sunset.as_ref().and_then(date_from_toml) {
+            Some(sunset) if today >= sunset => {
+                format!("reached end-of-life on {sunset} and no longer receives updates")
+            }
+            Some(sunset) => format!("is deprecated and reaches end-of-life on {sunset}"),
+            None => String::from("is deprecated"),
+        };
+        Some(format!(
+            "The {stack} stack ({distro_name} {distro_version}) {status}. \
+             Upgrade to a newer stack to continue receiving Ruby and security updates."
+        ))
+    }
+}
+
+/// Every target that Ruby binaries are built for
+#[cfg(test)]
+pub(crate) fn all_target_ids() -> Vec<TargetId> {
+    TARGETS
+        .iter()
+        .map(|target| TargetId {
+            distro_name: target.distro_name.clone(),
+            distro_version: target.distro_version.clone(),
+            cpu_architecture: target.arch.clone(),
+        })
+        .collect()
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
 
+    fn date(year: i32, month: u32, day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(year, month, day).unwrap()
+    }
+
+    fn target_id(distro_version: &str, cpu_architecture: &str) -> TargetId {
+        TargetId {
+            cpu_architecture: String::from(cpu_architecture),
+            distro_name: String::from("ubuntu"),
+            distro_version: String::from(distro_version),
+        }
+    }
+
     #[test]
-    fn test_arch_aware_versions_are_also_known_as_a_stack() {
-        for version in ARCH_AWARE_VERSIONS {
-            assert!(DISTRO_VERSION_STACK.iter().any(|&(_, v, _)| &v == version));
+    fn test_targets_match_buildpack_toml() {
+        let buildpack_toml: toml::Value =
+            toml::from_str(include_str!("../buildpack.toml")).unwrap();
+        let mut declared = Vec::new();
+        for target in buildpack_toml["targets"].as_array().unwrap() {
+            for distro in target["distros"].as_array().unwrap() {
+                declared.push((
+                    distro["name"].as_str().unwrap().to_string(),
+                    distro["version"].as_str().unwrap().to_string(),
+                    target["arch"].as_str().unwrap().to_string(),
+                ));
+            }
         }
+        let mut known = TARGETS
+            .iter()
+            .map(|target| {
+                (
+                    target.distro_name.clone(),
+                    target.distro_version.clone(),
+                    target.arch.clone(),
+                )
+            })
+            .collect::<Vec<_>>();
+        declared.sort();
+        known.sort();
+        assert_eq!(declared, known);
+    }
+
+    #[test]
+    fn test_target() {
+        assert_eq!(
+            UrlLayout::StackArch,
+            target_id("24.04", "arm64").target().unwrap().url_layout
+        );
+        assert_eq!(
+            UrlLayout::Stack,
+            target_id("22.04", "amd64").target().unwrap().url_layout
+        );
+        assert_eq!(
+            "CPU architecture 'arm64' is not supported on 'ubuntu-22.04'. Must be one of: 'amd64'",
+            target_id("22.04", "arm64")
+                .target()
+                .unwrap_err()
+                .to_string()
+        );
+        assert!(matches!(
+            target_id("18.04", "amd64").target(),
+            Err(TargetIdError::UnknownDistroNameVersionCombo(..))
+        ));
+    }
+
+    #[test]
+    fn test_deprecation_warning() {
+        let heroku_20 = target_id("20.04", "amd64").target().unwrap();
+        assert_eq!(
+            Some(String::from(
+                "The heroku-20 stack (ubuntu 20.04) is deprecated and reaches end-of-life on \
+                 2025-04-30. Upgrade to a newer stack to continue receiving Ruby and security \
+                 updates."
+            )),
+            heroku_20.deprecation_warning(date(2025, 1, 1))
+        );
+        assert!(heroku_20
+            .deprecation_warning(date(2025, 5, 1))
+            .unwrap()
+            .contains("reached end-of-life on 2025-04-30"));
+        assert_eq!(
+            None,
+            target_id("24.04", "amd64")
+                .target()
+                .unwrap()
+                .deprecation_warning(date(2025, 1, 1))
+        );
     }
 
     #[test]
@@ -71,8 +280,9 @@ mod test {
                 distro_name: String::from("ubuntu"),
                 distro_version: String::from("20.04"),
             }
-            .stack_name()
+            .target()
             .unwrap()
+            .stack
         );
 
         assert_eq!(
@@ -82,8 +292,9 @@ mod test {
                 distro_name: String::from("ubuntu"),
                 distro_version: String::from("22.04"),
             }
-            .stack_name()
+            .target()
             .unwrap()
+            .stack
         );
     }
 
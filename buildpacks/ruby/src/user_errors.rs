@@ -1,7 +1,13 @@
-use crate::{DetectError, RubyBuildpackError};
+use crate::layers::ruby_install_layer::{
+    CUSTOM_SHA256_ENV_KEY, CUSTOM_URL_ENV_KEY, MIRROR_ENV_KEY,
+};
+use crate::resolve_ruby::{VersionCheckError, VERSION_CHECK_ENV_KEY};
+use crate::ruby_inventory::Suggestions;
+use crate::{DetectError, RubyBuildpackError, RubyInstallError};
 use bullet_stream::{state::Bullet, state::SubBullet, style, Print};
 use fun_run::{CmdError, CommandWithName};
 use indoc::formatdoc;
+use std::fmt::Write;
 use std::io::Stdout;
 use std::process::Command;
 const DEBUG_INFO_STR: &str = "Debug info";
@@ -98,7 +104,22 @@ fn log_our_error(mut output: Print<Bullet<Stdout>>, error: RubyBuildpackError) {
                 debug using the above information and try again.
             "});
         }
+        RubyBuildpackError::BuildpackDetectionError(DetectError::RubyVersion(error)) => {
+            output.error(formatdoc! {"
+                Error: Ruby version file found with error
+
+                There was an error trying to read the contents of the application's .ruby-version \
+                or .tool-versions. The buildpack cannot continue if these files are unreadable.
+
+                {error}
+
+                Debug using the above information and try again.
+            "});
+        }
         RubyBuildpackError::MissingGemfileLock(path, error) => {
+            let lockfile = path
+                .file_name()
+                .map_or_else(|| path.to_string_lossy(), |name| name.to_string_lossy());
             output = output
                 .bullet(format!(
                     "Could not find {}, details:",
@@ -118,21 +139,235 @@ fn log_our_error(mut output: Print<Bullet<Stdout>>, error: RubyBuildpackError) {
             }
 
             output.error(formatdoc! {"
-                Error: `Gemfile.lock` not found
+                Error: `{lockfile}` not found
 
-                A `Gemfile.lock` file is required and was not found in the root of your application.
+                A `{lockfile}` file is required and was not found in the root of your application.
 
-                If you have a `Gemfile.lock` in your application, ensure it is tracked in Git and
+                If you have a `{lockfile}` in your application, ensure it is tracked in Git and
                 that you’re pushing the correct branch.
 
                 For more information:
                 {git_branch_url}
             "});
         }
+        RubyBuildpackError::GemfileLockParseError(path, error) => {
+            let lockfile = path
+                .file_name()
+                .map_or_else(|| path.to_string_lossy(), |name| name.to_string_lossy());
+            output
+                .bullet(debug_info)
+                .sub_bullet(error.to_string())
+                .done()
+                .error(formatdoc! {"
+                    Error: `{lockfile}` could not be parsed
+
+                    The Ruby buildpack reads the `{lockfile}` to determine which Ruby and bundler
+                    versions to install, and which gems your application depends on. The file
+                    is not in a format that bundler produces.
+
+                    If the file contains merge conflict markers such as `<<<<<<<`, resolve the
+                    conflict, run `bundle install` locally, and commit the result.
+
+                    Use the information above to debug further.
+                "});
+        }
+        RubyBuildpackError::RubyRequirementError(path, error) => {
+            let file = path
+                .file_name()
+                .map_or_else(|| path.to_string_lossy(), |name| name.to_string_lossy());
+            output
+                .bullet(debug_info)
+                .sub_bullet(error.to_string())
+                .done()
+                .error(formatdoc! {"
+                    Error: Invalid Ruby version in `{file}`
+
+                    The Ruby buildpack reads the Ruby version your application requires from
+                    `{file}` but could not understand it. Use an exact version such as `3.3.6`,
+                    or a requirement such as `3.3` or `~> 3.3` to install the newest matching
+                    Ruby version.
+
+                    Supported ruby versions:
+                    {ruby_versions_url}
+                "});
+        }
+        RubyBuildpackError::RubyVersionCheckError(error) => {
+            let output = output
+                .bullet(debug_info)
+                .sub_bullet(error.to_string())
+                .done();
+            match error {
+                VersionCheckError::InvalidSetting(_) => output.error(formatdoc! {"
+                    Error: Invalid {VERSION_CHECK_ENV_KEY} value
+
+                    Set {VERSION_CHECK_ENV_KEY} to {warn} to print a warning when Ruby version
+                    sources disagree, or to {strict} to fail the build. Alternatively set
+                    {setting} in the {table} table of your {project_toml}.
+                    ",
+                    warn = style::value("warn"),
+                    strict = style::value("strict"),
+                    setting = style::value("ruby_version_check = \"strict\""),
+                    table = style::value("[com.heroku.buildpacks.ruby]"),
+                    project_toml = style::value("project.toml"),
+                }),
+                VersionCheckError::Conflicts(conflicts) => {
+                    let sources = conflicts
+                        .iter()
+                        .map(|conflict| {
+                            format!(
+                                "- {file} declares Ruby {declared}, {other_file} declares Ruby {other}",
+                                file = style::value(&conflict.file),
+                                declared = style::value(&conflict.declared),
+                                other_file = style::value(&conflict.other_file),
+                                other = style::value(&conflict.other),
+                            )
+                        })
+                        .collect::<Vec<_>>()
+                        .join("\n");
+                    output.error(formatdoc! {"
+                        Error: Ruby version sources disagree
+
+                        Your application declares its Ruby version in more than one place and
+                        they do not agree:
+
+                        {sources}
+
+                        Bundler would fail or install gems for a different Ruby version than the
+                        one used locally. Update these files so that they declare the same Ruby
+                        version, or remove the version from all but one of them. When using a
+                        {gemfile} requirement, run {bundle_update} to record the matching
+                        version in the lockfile.
+
+                        To print a warning instead of failing, set {VERSION_CHECK_ENV_KEY} to {warn}.
+                        ",
+                        gemfile = style::value("Gemfile"),
+                        bundle_update = style::command("bundle update --ruby"),
+                        warn = style::value("warn"),
+                    });
+                }
+            }
+        }
+        RubyBuildpackError::ProjectConfigError(error) => {
+            output
+                .bullet(debug_info)
+                .sub_bullet(error.to_string())
+                .done()
+                .error(formatdoc! {"
+                    Error: Invalid `project.toml`
+
+                    The Ruby buildpack reads its configuration from the `[com.heroku.buildpacks.ruby]`
+                    table of `project.toml` but could not load it. Fix the error above and try again.
+                "});
+        }
+        RubyBuildpackError::RubyInstallError(error @ RubyInstallError::InvalidMirror { .. }) => {
+            let env_key = style::value(MIRROR_ENV_KEY);
+            output
+                .bullet(debug_info)
+                .sub_bullet(error.to_string())
+                .done()
+                .error(formatdoc! {"
+                    Error: Invalid Ruby binary mirror
+
+                    A mirror to download Ruby binaries from was configured with the {env_key}
+                    environment variable or `binary_mirror` in `project.toml`, but it is not a
+                    valid URL. Use a URL such as `https://ruby-mirror.example.com` or a local
+                    directory such as `file:///srv/ruby`.
+                "});
+        }
+        RubyBuildpackError::RubyInstallError(error @ RubyInstallError::ChecksumMismatch { .. }) => {
+            output
+                .bullet(debug_info)
+                .sub_bullet(error.to_string())
+                .done()
+                .error(formatdoc! {"
+                    Error verifying Ruby download
+
+                    The downloaded Ruby archive does not match the checksum listed in the buildpack's
+                    inventory. The file may have been corrupted or tampered with in transit, so it
+                    was not installed.
+
+                    When installing a custom Ruby with {url_key}, make sure {sha_key} is the
+                    sha256 checksum of the file at that URL.
+
+                    Please retry your build. If the issue persists, open an issue on the buildpack's
+                    GitHub repository.
+                    ",
+                    url_key = style::value(CUSTOM_URL_ENV_KEY),
+                    sha_key = style::value(CUSTOM_SHA256_ENV_KEY),
+                });
+        }
+        RubyBuildpackError::RubyInstallError(
+            error @ RubyInstallError::InvalidCustomRuby { .. },
+        ) => {
+            output
+                .bullet(debug_info)
+                .sub_bullet(error.to_string())
+                .done()
+                .error(formatdoc! {"
+                    Error: Invalid custom Ruby
+
+                    A custom Ruby build was configured with the {url_key} and {sha_key}
+                    environment variables or `ruby_url` and `ruby_sha256` in `project.toml`, but
+                    it could not be used. Both a URL such as `https://example.com/ruby-3.3.6.tgz`
+                    and the sha256 checksum of that file are required. To compute the checksum run:
+
+                        $ curl -sSfL <url> | shasum -a 256
+                    ",
+                    url_key = style::value(CUSTOM_URL_ENV_KEY),
+                    sha_key = style::value(CUSTOM_SHA256_ENV_KEY),
+                });
+        }
+        RubyBuildpackError::RubyInstallError(error @ RubyInstallError::VerifyFailed(_)) => {
+            output
+                .bullet(debug_info)
+                .sub_bullet(error.to_string())
+                .done()
+                .error(formatdoc! {"
+                    Error: Installed Ruby does not work
+
+                    The Ruby buildpack runs the installed Ruby and loads the `openssl`, `psych`,
+                    and `zlib` libraries to make sure it works before installing gems. This check
+                    failed on a freshly downloaded Ruby.
+
+                    This usually means the Ruby binary was built for a different distribution or
+                    CPU architecture, for example when it is served from a misconfigured binary
+                    mirror. Please retry your build. If the issue persists, open an issue on the
+                    buildpack's GitHub repository.
+                "});
+        }
+        RubyBuildpackError::RubyInstallError(
+            ref error @ (RubyInstallError::NotInInventory {
+                ref suggestions,
+                ref target,
+                ..
+            }
+            | RubyInstallError::NoMatchingRuby {
+                ref suggestions,
+                ref target,
+                ..
+            }),
+        ) => {
+            let suggestions = suggestions_help(suggestions);
+            output
+                .bullet(debug_info)
+                .sub_bullet(error.to_string())
+                .done()
+                .error(formatdoc! {"
+                    Error: Ruby version not available
+
+                    The requested Ruby version is not available for {target}.
+                    {suggestions}
+                    Supported ruby versions:
+                    {ruby_versions_url}
+
+                    To install a Ruby build that is not listed, set {url_key} to the URL of the
+                    `.tgz` file and {sha_key} to its sha256 checksum.
+                    ",
+                    url_key = style::value(CUSTOM_URL_ENV_KEY),
+                    sha_key = style::value(CUSTOM_SHA256_ENV_KEY),
+                });
+        }
         RubyBuildpackError::RubyInstallError(error) => {
-            // Future:
-            // - In the future use a manifest file to list if version is available on a different stack
-            // - In the future add a "did you mean" Levenshtein distance to see if they typoed like "3.6.0" when they meant "3.0.6"
             output.bullet(debug_info)
                 .sub_bullet(error.to_string())
                 .error(formatdoc! {"
@@ -171,7 +406,7 @@ fn log_our_error(mut output: Print<Bullet<Stdout>>, error: RubyBuildpackError) {
             let local_command = local_command_debug(&error);
             output
                 .bullet(&debug_info)
-                .sub_bullet(error.to_string())
+                .sub_bullet(crate::gem_credentials::redact(&error.to_string()))
                 .done()
                 .error(formatdoc! {"
                     Error installing your applications's dependencies
@@ -322,8 +557,37 @@ fn cause(err: libcnb::Error<RubyBuildpackError>) -> Cause {
     }
 }
 
+/// Lists close versions and other targets where the requested Ruby is available
+fn suggestions_help(suggestions: &Suggestions) -> String {
+    let mut help = String::new();
+    if !suggestions.closest.is_empty() {
+        let versions = suggestions
+            .closest
+            .iter()
+            .map(|ruby| style::value(ruby.to_string()))
+            .collect::<Vec<_>>()
+            .join(", ");
+        let _ = writeln!(help, "\nDid you mean: {versions}");
+    }
+    if !suggestions.other_targets.is_empty() {
+        let targets = suggestions
+            .other_targets
+            .iter()
+            .map(|target| format!("- {target}"))
+            .collect::<Vec<_>>()
+            .join("\n");
+        let _ = writeln!(
+            help,
+            "\nThe requested Ruby is available on:\n\n{targets}\n\nTo use it, change the base image of your application to one of these targets."
+        );
+    }
+    help
+}
+
 fn local_command_debug(error: &CmdError) -> String {
-    let cmd_name = replace_app_path_with_relative(style::command(error.name()));
+    let cmd_name = replace_app_path_with_relative(style::command(crate::gem_credentials::redact(
+        &error.name(),
+    )));
 
     formatdoc! {"
         Ensure you can run the following command locally with no errors before attempting another build:
@@ -353,6 +617,116 @@ fn debug_cmd(mut log: Print<SubBullet<Stdout>>, command: &mut Command) -> Print<
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::layers::ruby_install_layer::{download_url, BinaryMirror};
+    use crate::ruby_inventory::Inventory;
+    use crate::steps::DEFAULT_RUBY_VERSION;
+    use crate::target_id::{all_target_ids, TargetId};
+    use commons::gem_version::GemVersion;
+    use commons::gemfile_lock::ResolvedRuby;
+    use commons::ruby_requirement::RubyRequirement;
+    use std::str::FromStr;
+
+    /// An inventory in the format of `inventory.toml` listing `versions` for every supported target
+    fn populated_inventory(versions: &[&str]) -> Inventory {
+        let mut inventory = String::new();
+        for target in all_target_ids() {
+            for version in versions {
+                let url = download_url(
+                    &BinaryMirror::default(),
+                    &target,
+                    &ResolvedRuby::mri(version),
+                )
+                .unwrap();
+                inventory.push_str(&formatdoc! {r#"
+                    [[artifacts]]
+                    version = "{version}"
+                    engine = "ruby"
+                    engine_version = "{version}"
+                    distro_name = "{distro_name}"
+                    distro_version = "{distro_version}"
+                    arch = "{arch}"
+                    url = "{url}"
+                    sha256 = "{sha256}"
+
+                    "#,
+                    distro_name = target.distro_name,
+                    distro_version = target.distro_version,
+                    arch = target.cpu_architecture,
+                    sha256 = "0".repeat(64),
+                });
+            }
+        }
+        toml::from_str(&inventory).unwrap()
+    }
+
+    #[test]
+    fn test_suggestions_help() {
+        assert_eq!("", suggestions_help(&Suggestions::default()));
+
+        let help = suggestions_help(&Suggestions {
+            closest: vec![ResolvedRuby::mri("3.0.6")],
+            other_targets: vec![TargetId {
+                distro_name: String::from("ubuntu"),
+                distro_version: String::from("24.04"),
+                cpu_architecture: String::from("arm64"),
+            }],
+        });
+        assert!(help.contains("Did you mean"));
+        assert!(help.contains("3.0.6"));
+        assert!(help.contains("- ubuntu 24.04 (arm64)"));
+    }
+
+    #[test]
+    fn test_suggestions_from_populated_inventory() {
+        let mut inventory = populated_inventory(&[DEFAULT_RUBY_VERSION, "3.3.5", "3.3.6"]);
+        let targets = all_target_ids();
+        let (target, other_targets) = targets.split_first().unwrap();
+        inventory
+            .artifacts
+            .retain(|artifact| !(&artifact.target_id() == target && artifact.version.0 == "3.3.6"));
+
+        let help = suggestions_help(&inventory.suggest(target, &ResolvedRuby::mri("3.3.6")));
+        assert!(help.contains("Did you mean"), "{help}");
+        assert!(help.contains("3.3.5"), "{help}");
+        for other in other_targets {
+            assert!(help.contains(&format!("- {other}")), "{help}");
+        }
+
+        let help = suggestions_help(
+            &inventory
+                .suggest_for_requirement(target, &RubyRequirement::from_str("~> 3.3.6").unwrap()),
+        );
+        assert!(help.contains("3.3.5"), "{help}");
+        assert!(
+            help.contains("The requested Ruby is available on"),
+            "{help}"
+        );
+
+        let help = suggestions_help(&inventory.suggest(target, &ResolvedRuby::mri("1.8.7")));
+        assert!(
+            !help.contains("The requested Ruby is available on"),
+            "{help}"
+        );
+    }
+
+    #[test]
+    #[ignore = "inventory.toml does not list checksummed artifacts yet"]
+    fn test_shipped_inventory_suggests_default_ruby() {
+        let inventory = Inventory::shipped().unwrap();
+        let default = ResolvedRuby::mri(DEFAULT_RUBY_VERSION);
+        // A patch release of the default series that does not exist
+        let requested = ResolvedRuby::mri(&format!(
+            "{}.99",
+            GemVersion::from_str(DEFAULT_RUBY_VERSION).unwrap().series()
+        ));
+        for target in all_target_ids() {
+            let help = suggestions_help(&inventory.suggest(&target, &requested));
+            assert!(
+                help.contains(&default.to_string()),
+                "Expected {default} to be suggested for {requested} on {target}: {help:?}"
+            );
+        }
+    }
 
     #[test]
     fn test_relative_path() {
@@ -362,4 +736,17 @@ mod test {
         );
         assert_eq!(expected, &actual);
     }
+
+    #[test]
+    fn test_local_command_debug_redacts_credentials() {
+        let error = CmdError::SystemError(
+            String::from(
+                r#"BUNDLE_GEMS__EXAMPLE__COM="deploy:token" BUNDLE_GEMFILE="/workspace/Gemfile" bundle install"#,
+            ),
+            std::io::Error::other("bundle not found"),
+        );
+        let debug = local_command_debug(&error);
+        assert!(debug.contains(r#"BUNDLE_GEMS__EXAMPLE__COM="[REDACTED]""#));
+        assert!(!debug.contains("deploy:token"));
+    }
 }
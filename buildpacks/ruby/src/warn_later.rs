@@ -0,0 +1,78 @@
+//! Warnings that are repeated at the end of the build so they are not lost in the scroll
+//!
+//! Like `commons::output::warn_later::WarnGuard`, warnings that have not been printed when the
+//! guard is dropped are printed then, so they are still shown when the build fails.
+use bullet_stream::state::Bullet;
+use bullet_stream::Print;
+use std::io::{Stdout, Write};
+
+#[derive(Debug)]
+pub(crate) struct WarnLater<W: Write> {
+    io: Option<W>,
+    warnings: Vec<String>,
+}
+
+impl WarnLater<Stdout> {
+    pub(crate) fn new() -> Self {
+        Self::with_io(std::io::stdout())
+    }
+}
+
+impl<W: Write> WarnLater<W> {
+    fn with_io(io: W) -> Self {
+        Self {
+            io: Some(io),
+            warnings: Vec::new(),
+        }
+    }
+
+    pub(crate) fn push(&mut self, warning: impl Into<String>) {
+        self.warnings.push(warning.into());
+    }
+
+    /// Prints the warnings at the end of a successful build, before it is done
+    pub(crate) fn warn_now<O: Write>(mut self, mut output: Print<Bullet<O>>) -> Print<Bullet<O>> {
+        for warning in std::mem::take(&mut self.warnings) {
+            output = output.warning(warning);
+        }
+        output
+    }
+}
+
+impl<W: Write> Drop for WarnLater<W> {
+    fn drop(&mut self) {
+        if let (false, Some(io)) = (self.warnings.is_empty(), self.io.take()) {
+            let mut output = Print::new(io).without_header();
+            for warning in std::mem::take(&mut self.warnings) {
+                output = output.warning(warning);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_warnings_are_printed_on_drop() {
+        let mut io = Vec::new();
+        {
+            let mut warn_later = WarnLater::with_io(&mut io);
+            warn_later.push("Ruby 2.7.8 is past its end-of-life.");
+        }
+        assert!(String::from_utf8_lossy(&io).contains("Ruby 2.7.8 is past its end-of-life."));
+    }
+
+    #[test]
+    fn test_warn_now_prints_once() {
+        let mut io = Vec::new();
+        let mut warn_later = WarnLater::with_io(&mut io);
+        warn_later.push("Ruby 2.7.8 is past its end-of-life.");
+        let output = warn_later.warn_now(Print::new(Vec::new()).without_header());
+        let printed = String::from_utf8_lossy(&output.done()).to_string();
+
+        assert!(printed.contains("Ruby 2.7.8 is past its end-of-life."));
+        assert!(io.is_empty());
+    }
+}
@@ -19,7 +19,7 @@ use std::str::FromStr; // needed for lookahead/behind
 /// let version = GemVersion::from_str("1.0.0").unwrap();
 /// assert!(version < GemVersion::from_str("2.0.0").unwrap());
 /// ```
-#[derive(Debug, Default)]
+#[derive(Debug, Default, Clone)]
 pub struct GemVersion {
     segments: Vec<VersionSegment>,
 }
@@ -96,6 +96,28 @@ impl FromStr for GemVersion {
     }
 }
 
+impl GemVersion {
+    /// A version is a prerelease if any segment contains a letter i.e. `3.4.0.preview2`
+    #[must_use]
+    pub fn is_prerelease(&self) -> bool {
+        self.segments
+            .iter()
+            .any(|segment| matches!(segment, VersionSegment::String(_)))
+    }
+
+    /// The release series, the major and minor version i.e. `3.3` for `3.3.6` or `3.4.0.preview2`
+    #[must_use]
+    pub fn series(&self) -> String {
+        let mut numbers = self.segments.iter().map_while(|segment| match segment {
+            VersionSegment::U32(number) => Some(*number),
+            VersionSegment::String(_) => None,
+        });
+        let major = numbers.next().unwrap_or_default();
+        let minor = numbers.next().unwrap_or_default();
+        format!("{major}.{minor}")
+    }
+}
+
 impl PartialEq<GemVersion> for GemVersion {
     fn eq(&self, other: &Self) -> bool {
         self.partial_cmp(other) == Some(Ordering::Equal)
@@ -137,7 +159,7 @@ pub enum VersionError {
     InvalidVersion(String),
 }
 
-#[derive(Debug, Eq, PartialEq)]
+#[derive(Debug, Eq, PartialEq, Clone)]
 enum VersionSegment {
     String(String),
     U32(u32),
@@ -223,6 +245,22 @@ mod test {
         );
     }
 
+    #[test]
+    fn prerelease() {
+        assert!(v("3.4.0.preview2").is_prerelease());
+        assert!(v("3.3.0.rc1").is_prerelease());
+        assert!(!v("3.3.0").is_prerelease());
+    }
+
+    #[test]
+    fn series() {
+        assert_eq!("3.3", v("3.3.6").series());
+        assert_eq!("3.0", v("3.0.0").series());
+        assert_eq!("3.4", v("3.4.0.preview2").series());
+        assert_eq!("9.4", v("9.4.8.0").series());
+        assert_eq!("24.0", v("24.0.0").series());
+    }
+
     // Test helper method
     fn v(s: &str) -> GemVersion {
         s.parse().unwrap()
@@ -0,0 +1,832 @@
+use std::iter::Peekable;
+use std::str::Chars;
+
+/// # Statically read a `Gemfile`
+///
+/// A `Gemfile` is Ruby code, reading it fully requires Ruby and the application's dependencies.
+/// Before Ruby is installed, a safe subset of the DSL is read without evaluating anything:
+///
+/// - `source "https://rubygems.org"`, including `source "..." do` blocks
+/// - `ruby "3.3.6"`, `ruby "~> 3.3", engine: "jruby", engine_version: "9.4.8.0"`, and
+///   `ruby file: ".ruby-version"`
+/// - `group :development, :test do` blocks
+/// - `gem "rails", "~> 7.1", require: false, group: :assets`
+///
+/// Arguments must be string, symbol, integer, boolean, or `nil` literals, or arrays of them.
+/// Any other statement, such as a method call, a variable, string interpolation, or a conditional,
+/// is reported in `unsupported` and nothing is inferred from it. The contents of blocks that
+/// cannot be evaluated, such as `if` or `platforms`, are skipped.
+///
+/// ```rust
+/// use commons::gemfile::{Gemfile, RubyDirective};
+///
+/// let gemfile = Gemfile::parse(r#"
+/// source "https://rubygems.org"
+/// ruby "~> 3.3"
+///
+/// gem "rails"
+/// group :development, :test do
+///   gem "rspec-rails"
+/// end
+/// gem "debug" if ENV["DEBUG"]
+/// "#);
+///
+/// assert_eq!(gemfile.sources, vec!["https://rubygems.org"]);
+/// assert_eq!(
+///     gemfile.ruby,
+///     Some(RubyDirective::Version {
+///         requirements: vec![String::from("~> 3.3")],
+///         engine: None,
+///         engine_version: None,
+///         patchlevel: None,
+///     })
+/// );
+/// assert_eq!(gemfile.gems[1].groups, vec!["development", "test"]);
+/// assert_eq!(gemfile.gems.len(), 2);
+/// assert_eq!(
+///     gemfile.unsupported[0].to_string(),
+///     r#"Line 9: cannot evaluate condition `gem "debug" if ENV["DEBUG"]`"#
+/// );
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Gemfile {
+    /// Global gem sources in order of declaration
+    pub sources: Vec<String>,
+    pub ruby: Option<RubyDirective>,
+    pub gems: Vec<Gem>,
+    /// Statements that cannot be evaluated statically
+    pub unsupported: Vec<Unsupported>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RubyDirective {
+    /// Version requirements such as `ruby ">= 3.1", "< 3.4"`
+    Version {
+        requirements: Vec<String>,
+        engine: Option<String>,
+        engine_version: Option<String>,
+        patchlevel: Option<String>,
+    },
+    /// The version is read from a file such as `ruby file: ".ruby-version"`
+    File(String),
+}
+
+/// A `gem` declaration
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Gem {
+    pub name: String,
+    pub requirements: Vec<String>,
+    /// Groups from enclosing `group` blocks and the `group:` or `groups:` option, `default`
+    /// when there are none
+    pub groups: Vec<String>,
+    /// Set by an enclosing `source` block or the `source:` option
+    pub source: Option<String>,
+    /// Remaining options such as `require: false` or `git: "..."`
+    pub options: Vec<(String, Value)>,
+    /// One indexed line number of the declaration
+    pub line: usize,
+}
+
+/// A literal argument value
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Value {
+    String(String),
+    Symbol(String),
+    Integer(i64),
+    Bool(bool),
+    Nil,
+    Array(Vec<Value>),
+}
+
+/// A statement that cannot be evaluated, the first value is the (one indexed) line number
+#[derive(thiserror::Error, Debug, PartialEq, Eq, Clone)]
+pub enum Unsupported {
+    #[error("Line {0}: cannot parse `{1}`")]
+    Syntax(usize, String),
+
+    #[error("Line {0}: unsupported method `{1}`")]
+    Method(usize, String),
+
+    #[error("Line {0}: cannot evaluate expression `{1}`")]
+    Expression(usize, String),
+
+    #[error("Line {0}: cannot evaluate condition `{1}`")]
+    Conditional(usize, String),
+
+    #[error("Line {0}: cannot evaluate block `{1}`, its contents are skipped")]
+    Block(usize, String),
+
+    #[error("Line {0}: unexpected arguments `{1}`")]
+    Arguments(usize, String),
+
+    #[error("Line {0}: Ruby version is already declared `{1}`")]
+    DuplicateRuby(usize, String),
+}
+
+impl Gemfile {
+    /// Reads the supported subset of the DSL, never fails
+    #[must_use]
+    pub fn parse(contents: &str) -> Self {
+        let mut gemfile = Gemfile::default();
+        let mut blocks: Vec<(usize, Block)> = Vec::new();
+
+        for Statement { line, text, tokens } in statements(contents) {
+            let skipping = blocks
+                .iter()
+                .any(|(_, block)| matches!(block, Block::Skipped));
+            let Ok(tokens) = tokens else {
+                if !skipping {
+                    gemfile.unsupported.push(Unsupported::Syntax(line, text));
+                }
+                continue;
+            };
+            if tokens == [Token::Ident(String::from("end"))] {
+                if blocks.pop().is_none() {
+                    gemfile.unsupported.push(Unsupported::Syntax(line, text));
+                }
+                continue;
+            }
+
+            let opens_block = matches!(tokens.last(), Some(Token::Ident(word)) if word == "do")
+                || matches!(tokens.first(), Some(Token::Ident(word)) if BLOCK_KEYWORDS.contains(&word.as_str()));
+            if skipping {
+                if opens_block {
+                    blocks.push((line, Block::Skipped));
+                }
+                continue;
+            }
+            match gemfile.evaluate(line, &tokens, opens_block, &blocks) {
+                Ok(block) => blocks.extend(block.map(|block| (line, block))),
+                Err(reason) => {
+                    gemfile.unsupported.push(reason(line, text));
+                    if opens_block {
+                        blocks.push((line, Block::Skipped));
+                    }
+                }
+            }
+        }
+
+        for (line, _) in blocks {
+            gemfile.unsupported.push(Unsupported::Syntax(
+                line,
+                contents
+                    .lines()
+                    .nth(line - 1)
+                    .unwrap_or_default()
+                    .trim()
+                    .to_string(),
+            ));
+        }
+        gemfile
+    }
+
+    /// Applies a single statement, returns the block it opens if any
+    fn evaluate(
+        &mut self,
+        line: usize,
+        tokens: &[Token],
+        opens_block: bool,
+        blocks: &[(usize, Block)],
+    ) -> Result<Option<Block>, Reason> {
+        let Some((Token::Ident(method), rest)) = tokens.split_first() else {
+            return Err(Unsupported::Expression);
+        };
+        if BLOCK_KEYWORDS.contains(&method.as_str()) {
+            return Err(Unsupported::Block);
+        }
+        let rest = if opens_block {
+            &rest[..rest.len().saturating_sub(1)]
+        } else {
+            rest
+        };
+        if rest.iter().any(
+            |token| matches!(token, Token::Ident(word) if MODIFIER_KEYWORDS.contains(&word.as_str())),
+        ) {
+            return Err(Unsupported::Conditional);
+        }
+        let args = arguments(rest).map_err(|()| Unsupported::Expression as Reason)?;
+
+        match (method.as_str(), opens_block) {
+            ("gem", false) => {
+                let gem = gem(line, args, blocks).ok_or(Unsupported::Arguments as Reason)?;
+                self.gems.push(gem);
+                Ok(None)
+            }
+            ("ruby", false) => {
+                let directive = ruby(args).ok_or(Unsupported::Arguments as Reason)?;
+                if self.ruby.is_some() {
+                    return Err(Unsupported::DuplicateRuby);
+                }
+                self.ruby = Some(directive);
+                Ok(None)
+            }
+            ("source", _) => {
+                let [Arg::Positional(Value::String(url))] = args.as_slice() else {
+                    return Err(Unsupported::Arguments);
+                };
+                if opens_block {
+                    Ok(Some(Block::Source(url.clone())))
+                } else {
+                    self.sources.push(url.clone());
+                    Ok(None)
+                }
+            }
+            ("group", true) => group_names(&args)
+                .map(|names| Some(Block::Group(names)))
+                .ok_or(Unsupported::Arguments),
+            (_, true) => Err(Unsupported::Block),
+            (_, false) => Err(Unsupported::Method),
+        }
+    }
+
+    /// True when every statement was understood
+    #[must_use]
+    pub fn is_complete(&self) -> bool {
+        self.unsupported.is_empty()
+    }
+
+    /// Gems declared in a group, gems outside of any group are in `default`
+    pub fn gems_in_group<'a>(&'a self, group: &'a str) -> impl Iterator<Item = &'a Gem> {
+        self.gems
+            .iter()
+            .filter(move |gem| gem.groups.iter().any(|name| name == group))
+    }
+}
+
+/// Builds the reported statement from its line number and text
+type Reason = fn(usize, String) -> Unsupported;
+
+/// Keywords that open a block closed by `end`
+const BLOCK_KEYWORDS: [&str; 10] = [
+    "if", "unless", "case", "while", "until", "begin", "def", "class", "module", "for",
+];
+/// Keywords that make a statement conditional
+const MODIFIER_KEYWORDS: [&str; 5] = ["if", "unless", "while", "until", "rescue"];
+
+enum Block {
+    Group(Vec<String>),
+    Source(String),
+    /// A block that cannot be evaluated, statements inside of it are ignored
+    Skipped,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    String(String),
+    Symbol(String),
+    Integer(i64),
+    Ident(String),
+    /// A hash key such as `require:`
+    Label(String),
+    Comma,
+    Arrow,
+    OpenParen,
+    CloseParen,
+    OpenBracket,
+    CloseBracket,
+    /// Anything else such as `.`, `{`, or `|`
+    Other(char),
+}
+
+struct Statement {
+    line: usize,
+    text: String,
+    tokens: Result<Vec<Token>, ()>,
+}
+
+/// Splits the contents into statements, a statement continues onto the next line after a
+/// trailing comma, `=>`, `\`, or an open bracket
+fn statements(contents: &str) -> Vec<Statement> {
+    let mut statements = Vec::new();
+    let mut pending: Option<(usize, String, Vec<Token>)> = None;
+
+    for (index, raw) in contents.lines().enumerate() {
+        let (line, mut text, mut tokens) = pending
+            .take()
+            .unwrap_or_else(|| (index + 1, String::new(), Vec::new()));
+        let trimmed = raw.trim();
+        let continued = trimmed.ends_with('\\');
+        let source = trimmed.trim_end_matches('\\');
+        if !text.is_empty() && !trimmed.is_empty() {
+            text.push(' ');
+        }
+        text.push_str(source.trim());
+
+        let Ok(line_tokens) = tokenize(source) else {
+            statements.push(Statement {
+                line,
+                text: text.trim().to_string(),
+                tokens: Err(()),
+            });
+            continue;
+        };
+        tokens.extend(line_tokens);
+
+        let depth = tokens.iter().fold(0_i32, |depth, token| match token {
+            Token::OpenParen | Token::OpenBracket => depth + 1,
+            Token::CloseParen | Token::CloseBracket => depth - 1,
+            _ => depth,
+        });
+        if continued || depth > 0 || matches!(tokens.last(), Some(Token::Comma | Token::Arrow)) {
+            pending = Some((line, text, tokens));
+        } else if !tokens.is_empty() {
+            let text = text
+                .split(" #")
+                .next()
+                .unwrap_or_default()
+                .trim()
+                .to_string();
+            statements.push(Statement {
+                line,
+                text,
+                tokens: Ok(tokens),
+            });
+        }
+    }
+    if let Some((line, text, _)) = pending {
+        statements.push(Statement {
+            line,
+            text,
+            tokens: Err(()),
+        });
+    }
+    statements
+}
+
+fn tokenize(line: &str) -> Result<Vec<Token>, ()> {
+    let mut tokens = Vec::new();
+    let mut chars = line.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        match c {
+            ' ' | '\t' => {
+                chars.next();
+            }
+            '#' => break,
+            '\'' | '"' => {
+                chars.next();
+                tokens.push(string(&mut chars, c)?.map_or(Token::Other(c), Token::String));
+            }
+            ':' => {
+                chars.next();
+                match chars.peek() {
+                    Some('\'' | '"') => {
+                        let quote = chars.next().ok_or(())?;
+                        tokens.push(
+                            string(&mut chars, quote)?.map_or(Token::Other(quote), Token::Symbol),
+                        );
+                    }
+                    Some(c) if c.is_alphabetic() || *c == '_' => {
+                        tokens.push(Token::Symbol(identifier(&mut chars)));
+                    }
+                    _ => tokens.push(Token::Other(':')),
+                }
+            }
+            '=' => {
+                chars.next();
+                if chars.next_if_eq(&'>').is_some() {
+                    tokens.push(Token::Arrow);
+                } else {
+                    tokens.push(Token::Other('='));
+                }
+            }
+            ',' => {
+                chars.next();
+                tokens.push(Token::Comma);
+            }
+            '(' => {
+                chars.next();
+                tokens.push(Token::OpenParen);
+            }
+            ')' => {
+                chars.next();
+                tokens.push(Token::CloseParen);
+            }
+            '[' => {
+                chars.next();
+                tokens.push(Token::OpenBracket);
+            }
+            ']' => {
+                chars.next();
+                tokens.push(Token::CloseBracket);
+            }
+            c if c.is_ascii_digit() => {
+                let mut number = String::new();
+                while let Some(digit) = chars.next_if(|c| c.is_ascii_digit() || *c == '_') {
+                    number.push(digit);
+                }
+                tokens.push(Token::Integer(
+                    number.replace('_', "").parse().map_err(|_| ())?,
+                ));
+            }
+            c if c.is_alphabetic() || c == '_' => {
+                let word = identifier(&mut chars);
+                // `key:` is a label, `Foo::Bar` is a constant lookup
+                if chars.peek() == Some(&':') && chars.clone().nth(1) != Some(':') {
+                    chars.next();
+                    tokens.push(Token::Label(word));
+                } else {
+                    tokens.push(Token::Ident(word));
+                }
+            }
+            c => {
+                chars.next();
+                tokens.push(Token::Other(c));
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+fn identifier(chars: &mut Peekable<Chars<'_>>) -> String {
+    let mut word = String::new();
+    while let Some(c) = chars.next_if(|c| c.is_alphanumeric() || *c == '_') {
+        word.push(c);
+    }
+    if let Some(c) = chars.next_if(|c| *c == '?' || *c == '!') {
+        word.push(c);
+    }
+    word
+}
+
+/// Reads a string literal after its opening quote, `None` when it contains interpolation or an
+/// escape sequence that would need to be evaluated
+fn string(chars: &mut Peekable<Chars<'_>>, quote: char) -> Result<Option<String>, ()> {
+    let mut value = Some(String::new());
+    let mut depth = 0;
+    loop {
+        match chars.next().ok_or(())? {
+            c if c == quote && depth == 0 => return Ok(value),
+            '\\' => {
+                let escaped = chars.next().ok_or(())?;
+                match escaped {
+                    c if c == quote || c == '\\' => value.iter_mut().for_each(|v| v.push(c)),
+                    c if quote == '\'' => value.iter_mut().for_each(|v| {
+                        v.push('\\');
+                        v.push(c);
+                    }),
+                    _ => value = None,
+                }
+            }
+            '#' if quote == '"' && chars.next_if_eq(&'{').is_some() => {
+                value = None;
+                depth += 1;
+            }
+            '{' if depth > 0 => depth += 1,
+            '}' if depth > 0 => depth -= 1,
+            c => value.iter_mut().for_each(|v| v.push(c)),
+        }
+    }
+}
+
+enum Arg {
+    Positional(Value),
+    Keyword(String, Value),
+}
+
+/// Parses `a, b, key: value` with or without surrounding parentheses
+fn arguments(tokens: &[Token]) -> Result<Vec<Arg>, ()> {
+    let tokens = match tokens {
+        [Token::OpenParen, inner @ .., Token::CloseParen] => inner,
+        tokens => tokens,
+    };
+    let mut args = Vec::new();
+    let mut rest = tokens;
+    while !rest.is_empty() {
+        let arg = match rest {
+            [Token::Label(key), tail @ ..]
+            | [Token::Symbol(key) | Token::String(key), Token::Arrow, tail @ ..] => {
+                let (value, tail) = value(tail)?;
+                rest = tail;
+                Arg::Keyword(key.clone(), value)
+            }
+            tokens => {
+                let (value, tail) = value(tokens)?;
+                rest = tail;
+                Arg::Positional(value)
+            }
+        };
+        args.push(arg);
+        rest = match rest {
+            [] => rest,
+            [Token::Comma, tail @ ..] if !tail.is_empty() => tail,
+            _ => return Err(()),
+        };
+    }
+    Ok(args)
+}
+
+fn value(tokens: &[Token]) -> Result<(Value, &[Token]), ()> {
+    match tokens {
+        [Token::String(value), rest @ ..] => Ok((Value::String(value.clone()), rest)),
+        [Token::Symbol(value), rest @ ..] => Ok((Value::Symbol(value.clone()), rest)),
+        [Token::Integer(value), rest @ ..] => Ok((Value::Integer(*value), rest)),
+        [Token::Ident(word), rest @ ..] => match word.as_str() {
+            "true" => Ok((Value::Bool(true), rest)),
+            "false" => Ok((Value::Bool(false), rest)),
+            "nil" => Ok((Value::Nil, rest)),
+            _ => Err(()),
+        },
+        [Token::OpenBracket, rest @ ..] => {
+            let mut values = Vec::new();
+            let mut rest = rest;
+            loop {
+                if let [Token::CloseBracket, tail @ ..] = rest {
+                    return Ok((Value::Array(values), tail));
+                }
+                let (value, tail) = value(rest)?;
+                values.push(value);
+                rest = match tail {
+                    [Token::Comma, tail @ ..] => tail,
+                    [Token::CloseBracket, ..] => tail,
+                    _ => return Err(()),
+                };
+            }
+        }
+        _ => Err(()),
+    }
+}
+
+/// Names from a symbol, string, or an array of them
+fn names(value: &Value) -> Option<Vec<String>> {
+    match value {
+        Value::String(name) | Value::Symbol(name) => Some(vec![name.clone()]),
+        Value::Array(values) => values.iter().try_fold(Vec::new(), |mut names, value| {
+            names.extend(self::names(value).filter(|_| !matches!(value, Value::Array(_)))?);
+            Some(names)
+        }),
+        _ => None,
+    }
+}
+
+fn group_names(args: &[Arg]) -> Option<Vec<String>> {
+    let mut groups = Vec::new();
+    for arg in args {
+        match arg {
+            Arg::Positional(value @ (Value::String(_) | Value::Symbol(_))) => {
+                groups.extend(names(value)?);
+            }
+            // Options such as `optional: true` do not change group membership
+            Arg::Keyword(..) => {}
+            Arg::Positional(_) => return None,
+        }
+    }
+    (!groups.is_empty()).then_some(groups)
+}
+
+fn gem(line: usize, args: Vec<Arg>, blocks: &[(usize, Block)]) -> Option<Gem> {
+    let mut args = args.into_iter();
+    let Some(Arg::Positional(Value::String(name))) = args.next() else {
+        return None;
+    };
+    let mut groups = blocks
+        .iter()
+        .filter_map(|(_, block)| match block {
+            Block::Group(names) => Some(names.clone()),
+            _ => None,
+        })
+        .flatten()
+        .collect::<Vec<_>>();
+    let mut source = blocks.iter().rev().find_map(|(_, block)| match block {
+        Block::Source(url) => Some(url.clone()),
+        _ => None,
+    });
+    let mut requirements = Vec::new();
+    let mut options = Vec::new();
+    for arg in args {
+        match arg {
+            Arg::Positional(Value::String(requirement)) if options.is_empty() => {
+                requirements.push(requirement);
+            }
+            Arg::Keyword(key, value) if key == "group" || key == "groups" => {
+                groups.extend(names(&value)?);
+            }
+            Arg::Keyword(key, Value::String(url)) if key == "source" => source = Some(url),
+            Arg::Keyword(key, value) => options.push((key, value)),
+            Arg::Positional(_) => return None,
+        }
+    }
+    if groups.is_empty() {
+        groups.push(String::from("default"));
+    }
+    groups.dedup();
+    Some(Gem {
+        name,
+        requirements,
+        groups,
+        source,
+        options,
+        line,
+    })
+}
+
+fn ruby(args: Vec<Arg>) -> Option<RubyDirective> {
+    let mut requirements = Vec::new();
+    let mut engine = None;
+    let mut engine_version = None;
+    let mut patchlevel = None;
+    let mut file = None;
+    for arg in args {
+        match arg {
+            Arg::Positional(Value::String(requirement)) => requirements.push(requirement),
+            Arg::Keyword(key, Value::String(value)) => match key.as_str() {
+                "engine" => engine = Some(value),
+                "engine_version" => engine_version = Some(value),
+                "patchlevel" => patchlevel = Some(value),
+                "file" => file = Some(value),
+                _ => return None,
+            },
+            _ => return None,
+        }
+    }
+    match (file, requirements.is_empty()) {
+        (Some(file), true) => Some(RubyDirective::File(file)),
+        (None, false) => Some(RubyDirective::Version {
+            requirements,
+            engine,
+            engine_version,
+            patchlevel,
+        }),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use indoc::indoc;
+
+    fn version(requirements: &[&str]) -> RubyDirective {
+        RubyDirective::Version {
+            requirements: requirements.iter().map(ToString::to_string).collect(),
+            engine: None,
+            engine_version: None,
+            patchlevel: None,
+        }
+    }
+
+    #[test]
+    fn test_ruby_directive() {
+        for (line, expected) in [
+            ("ruby '3.3.6'", Some(version(&["3.3.6"]))),
+            (
+                "ruby \"~> 3.3\" # Patch releases",
+                Some(version(&["~> 3.3"])),
+            ),
+            ("ruby(\"~> 3.3\")", Some(version(&["~> 3.3"]))),
+            (
+                "ruby \">= 3.1\", \"< 3.4\"",
+                Some(version(&[">= 3.1", "< 3.4"])),
+            ),
+            (
+                "ruby '2.6.8', engine: 'jruby', :engine_version => '9.3.6.0'",
+                Some(RubyDirective::Version {
+                    requirements: vec![String::from("2.6.8")],
+                    engine: Some(String::from("jruby")),
+                    engine_version: Some(String::from("9.3.6.0")),
+                    patchlevel: None,
+                }),
+            ),
+            (
+                "ruby file: '.ruby-version'",
+                Some(RubyDirective::File(String::from(".ruby-version"))),
+            ),
+            ("ruby File.read('.ruby-version').strip", None),
+            ("ruby RUBY_VERSION", None),
+            ("ruby \"#{RUBY_VERSION}\"", None),
+        ] {
+            let gemfile = Gemfile::parse(&format!(
+                "source 'https://rubygems.org'\n\n{line}\ngem 'rails'\n"
+            ));
+            assert_eq!(expected, gemfile.ruby, "Parsing {line:?}");
+            assert_eq!(expected.is_some(), gemfile.is_complete(), "{gemfile:?}");
+            assert_eq!(1, gemfile.gems.len());
+        }
+    }
+
+    #[test]
+    fn test_gems_groups_and_sources() {
+        let gemfile = Gemfile::parse(indoc! {r#"
+            source "https://rubygems.org"
+            git_source(:github) { |repo| "https://github.com/#{repo}.git" }
+
+            gem "rails", "~> 7.1", ">= 7.1.3"
+            gem "bootsnap", require: false
+            gem "sassc-rails", group: :assets
+            gem "tzinfo-data", platforms: %i[ windows jruby ]
+
+            group :development, :test do
+              gem "rspec-rails",
+                "~> 6.1"
+
+              group :test do
+                gem "capybara", groups: [:system, "browser"]
+              end
+            end
+
+            source "https://gems.example.com" do
+              gem "private-gem"
+            end
+
+            if ENV["DEBUG"]
+              gem "debug"
+              group :debug do
+                gem "pry"
+              end
+            end
+            gemspec
+        "#});
+
+        assert_eq!(vec!["https://rubygems.org"], gemfile.sources);
+        let gem = |name: &str| {
+            gemfile
+                .gems
+                .iter()
+                .find(|gem| gem.name == name)
+                .unwrap_or_else(|| panic!("{name} not found in {gemfile:?}"))
+        };
+
+        let rails = gem("rails");
+        assert_eq!(vec!["~> 7.1", ">= 7.1.3"], rails.requirements);
+        assert_eq!(vec!["default"], rails.groups);
+        assert_eq!(4, rails.line);
+
+        assert_eq!(
+            vec![(String::from("require"), Value::Bool(false))],
+            gem("bootsnap").options
+        );
+        assert_eq!(vec!["assets"], gem("sassc-rails").groups);
+        assert_eq!(vec!["~> 6.1"], gem("rspec-rails").requirements);
+        assert_eq!(vec!["development", "test"], gem("rspec-rails").groups);
+        assert_eq!(
+            vec!["development", "test", "system", "browser"],
+            gem("capybara").groups
+        );
+        assert_eq!(
+            Some(String::from("https://gems.example.com")),
+            gem("private-gem").source
+        );
+        assert_eq!(
+            vec!["rspec-rails", "capybara"],
+            gemfile
+                .gems_in_group("test")
+                .map(|gem| gem.name.as_str())
+                .collect::<Vec<_>>()
+        );
+
+        // Nothing is guessed from statements that cannot be evaluated
+        assert!(!gemfile.gems.iter().any(|gem| gem.name == "tzinfo-data"));
+        assert!(!gemfile
+            .gems
+            .iter()
+            .any(|gem| gem.name == "debug" || gem.name == "pry"));
+        assert_eq!(
+            vec![
+                "Line 2: cannot evaluate expression `git_source(:github) { |repo| \"https://github.com/#{repo}.git\" }`",
+                "Line 7: cannot evaluate expression `gem \"tzinfo-data\", platforms: %i[ windows jruby ]`",
+                "Line 22: cannot evaluate block `if ENV[\"DEBUG\"]`, its contents are skipped",
+                "Line 28: unsupported method `gemspec`",
+            ],
+            gemfile
+                .unsupported
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn test_malformed() {
+        let gemfile = Gemfile::parse(indoc! {r#"
+            gem "rails"
+            end
+            gem
+            ruby "3.3.6"
+            ruby "3.3.5"
+            group :test do
+              gem "rspec", "
+        "#});
+        assert_eq!(
+            vec!["rails"],
+            gemfile
+                .gems
+                .iter()
+                .map(|gem| gem.name.as_str())
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(Some(version(&["3.3.6"])), gemfile.ruby);
+        assert_eq!(
+            vec![
+                "Line 2: cannot parse `end`",
+                "Line 3: unexpected arguments `gem`",
+                "Line 5: Ruby version is already declared `ruby \"3.3.5\"`",
+                "Line 7: cannot parse `gem \"rspec\", \"`",
+                "Line 6: cannot parse `group :test do`",
+            ],
+            gemfile
+                .unsupported
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>()
+        );
+    }
+}
@@ -1,13 +1,24 @@
+use crate::gem_version::GemVersion;
+use crate::ruby_requirement::RubyRequirement;
+use crate::ruby_version_file::VersionFile;
 use core::str::FromStr;
 use regex::Regex;
 use serde::{Deserialize, Serialize};
 use std::fmt::Display;
+use std::sync::LazyLock;
 
 /// # Parse and store contents of Gemfile.lock
 ///
 /// Before installing bundler or Ruby versions we first need information about the application.
 /// This struct holds both of these values. When no value is present it will return a `Default`
 /// enum.
+///
+/// The rest of the lockfile is also modeled: gem sources (`GEM`, `GIT`, `PATH`) with their
+/// resolved specs, `PLATFORMS`, `DEPENDENCIES`, and `CHECKSUMS`. A malformed file, for example
+/// one with leftover merge conflict markers, returns an error with the offending line number.
+///
+/// Version manager files such as `.ruby-version` are a fallback for the Ruby version when the
+/// lockfile does not have one, attach them with `with_version_files`.
 /// ```rust
 /// use core::str::FromStr;
 /// use commons::gemfile_lock::BundlerVersion;
@@ -39,19 +50,46 @@ use std::fmt::Display;
 ///     info.bundler_version,
 ///     BundlerVersion::Explicit("2.3.4".to_string())
 /// );
+/// assert!(info.has_gem("mini_histogram"));
 /// ```
 #[derive(Debug)]
 pub struct GemfileLock {
     pub bundler_version: BundlerVersion,
     pub ruby_version: RubyVersion,
+    pub sources: Vec<Source>,
+    pub platforms: Vec<String>,
+    pub dependencies: Vec<Dependency>,
+    pub checksums: Vec<Checksum>,
+    /// Version manager files in priority order, see `with_version_files`
+    pub version_files: Vec<VersionFile>,
 }
 
 impl GemfileLock {
+    /// All resolved gems from every source
+    pub fn specs(&self) -> impl Iterator<Item = &Spec> {
+        self.sources.iter().flat_map(|source| source.specs.iter())
+    }
+
+    /// True when the gem was resolved from any source, including transitive dependencies
+    #[must_use]
+    pub fn has_gem(&self, name: &str) -> bool {
+        self.specs().any(|spec| spec.name == name)
+    }
+
+    /// Fallback sources for the Ruby version when the lockfile does not have one
+    #[must_use]
+    pub fn with_version_files(mut self, version_files: Vec<VersionFile>) -> Self {
+        self.version_files = version_files;
+        self
+    }
+
+    /// The file the Ruby version comes from i.e. `Gemfile.lock` or `.ruby-version`, or `default`
     #[must_use]
     pub fn ruby_source(&self) -> String {
-        match self.ruby_version {
-            RubyVersion::Explicit(_) => String::from("Gemfile.lock"),
-            RubyVersion::Default => String::from("default"),
+        match (&self.ruby_version, self.version_files.first()) {
+            (RubyVersion::Explicit { .. }, _) => String::from("Gemfile.lock"),
+            (RubyVersion::Default, Some(version_file)) => version_file.file.to_string(),
+            (RubyVersion::Default, None) => String::from("default"),
         }
     }
 
@@ -63,11 +101,37 @@ impl GemfileLock {
         }
     }
 
+    /// Returns the Ruby to install, the default is an MRI version
+    ///
+    /// A version file is used when it names an exact version such as `3.3.6`. Requirements that
+    /// match several versions such as `3.3` must be resolved against the available versions, so
+    /// the default is returned for them.
     #[must_use]
-    pub fn resolve_ruby(&self, default: &str) -> ResolvedRubyVersion {
+    pub fn resolve_ruby(&self, default: &str) -> ResolvedRuby {
         match &self.ruby_version {
-            RubyVersion::Explicit(version) => ResolvedRubyVersion(version.to_string()),
-            RubyVersion::Default => ResolvedRubyVersion(default.to_string()),
+            RubyVersion::Explicit {
+                version,
+                engine,
+                engine_version,
+                ..
+            } => ResolvedRuby {
+                engine: *engine,
+                engine_version: engine_version.clone(),
+                version: ResolvedRubyVersion(version.clone()),
+            },
+            RubyVersion::Default => self
+                .version_files
+                .first()
+                .and_then(|version_file| {
+                    RubyRequirement::from_str(&version_file.requirement)
+                        .ok()?
+                        .exact()
+                        .map(ToString::to_string)
+                })
+                .map_or_else(
+                    || ResolvedRuby::mri(default),
+                    |version| ResolvedRuby::mri(&version),
+                ),
         }
     }
 
@@ -89,6 +153,86 @@ impl Display for ResolvedRubyVersion {
     }
 }
 
+/// A Ruby implementation, bundler refers to the reference implementation (MRI) as `ruby`
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum RubyEngine {
+    #[serde(rename = "ruby")]
+    Mri,
+    Jruby,
+    Truffleruby,
+}
+
+impl Display for RubyEngine {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            RubyEngine::Mri => "ruby",
+            RubyEngine::Jruby => "jruby",
+            RubyEngine::Truffleruby => "truffleruby",
+        })
+    }
+}
+
+impl FromStr for RubyEngine {
+    type Err = String;
+
+    fn from_str(engine: &str) -> Result<Self, Self::Err> {
+        match engine {
+            "ruby" => Ok(RubyEngine::Mri),
+            "jruby" => Ok(RubyEngine::Jruby),
+            "truffleruby" => Ok(RubyEngine::Truffleruby),
+            other => Err(other.to_string()),
+        }
+    }
+}
+
+/// The Ruby to install
+///
+/// Alternative engines such as `jruby` have their own version and declare compatibility with
+/// an MRI version, i.e. `jruby 9.4.8.0` is compatible with Ruby `3.1.4`. For MRI the engine
+/// version is the same as the Ruby version.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct ResolvedRuby {
+    pub engine: RubyEngine,
+    pub engine_version: String,
+    /// The MRI compatible version
+    pub version: ResolvedRubyVersion,
+}
+
+impl ResolvedRuby {
+    #[must_use]
+    pub fn mri(version: &str) -> Self {
+        Self {
+            engine: RubyEngine::Mri,
+            engine_version: version.to_string(),
+            version: ResolvedRubyVersion(version.to_string()),
+        }
+    }
+
+    /// Prior versions of this buildpack stored `jruby` versions as `<version>-jruby-<engine_version>`
+    #[must_use]
+    pub fn from_legacy_version(legacy: &ResolvedRubyVersion) -> Self {
+        match legacy.0.split_once("-jruby-") {
+            Some((version, engine_version)) => Self {
+                engine: RubyEngine::Jruby,
+                engine_version: engine_version.to_string(),
+                version: ResolvedRubyVersion(version.to_string()),
+            },
+            None => Self::mri(&legacy.0),
+        }
+    }
+}
+
+/// Formats as `3.3.0` for MRI and `3.1.4 (jruby 9.4.8.0)` for other engines
+impl Display for ResolvedRuby {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.engine {
+            RubyEngine::Mri => write!(f, "{}", self.version),
+            engine => write!(f, "{} ({engine} {})", self.version, self.engine_version),
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
 pub struct ResolvedBundlerVersion(pub String);
 
@@ -100,7 +244,17 @@ impl Display for ResolvedBundlerVersion {
 
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub enum RubyVersion {
-    Explicit(String),
+    /// A version from the `RUBY VERSION` section such as `ruby 3.4.0.preview2` or
+    /// `ruby 3.1.4p0 (jruby 9.4.8.0)`
+    Explicit {
+        /// The MRI compatible version, including any prerelease identifier such as `3.4.0.preview2`
+        version: String,
+        /// The patchlevel such as `0` from `3.3.0p0`, bundler writes `-1` for prereleases
+        patchlevel: Option<String>,
+        engine: RubyEngine,
+        /// Same as `version` for MRI
+        engine_version: String,
+    },
     Default,
 }
 
@@ -110,42 +264,339 @@ pub enum BundlerVersion {
     Default,
 }
 
+/// A source of gems such as a `GEM` (rubygems server), `GIT` repository or local `PATH`
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Source {
+    pub kind: SourceKind,
+    /// Value of the `remote:` lines, usually one
+    pub remotes: Vec<String>,
+    /// Additional `key: value` lines such as `revision`, `branch`, `ref`, `tag`, or `glob`
+    pub options: Vec<(String, String)>,
+    pub specs: Vec<Spec>,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum SourceKind {
+    Gem,
+    Git,
+    Path,
+    Plugin,
+}
+
+/// A resolved gem such as `nokogiri (1.16.0-x86_64-linux)` along with its dependencies
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Spec {
+    pub name: String,
+    pub version: String,
+    /// Set for platform specific gems such as `x86_64-linux`, `None` for pure Ruby gems
+    pub platform: Option<String>,
+    pub dependencies: Vec<Dependency>,
+}
+
+/// A gem name and version requirements such as `puma (>= 5.0, < 7)`
+///
+/// Entries in the `DEPENDENCIES` section that come from a `GIT` or `PATH`
+/// source are suffixed with a `!` and are marked as pinned.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Dependency {
+    pub name: String,
+    pub requirements: Vec<String>,
+    pub pinned: bool,
+}
+
+/// An entry of the `CHECKSUMS` section, digests are formatted `<algorithm>=<hex>`
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Checksum {
+    pub name: String,
+    pub version: String,
+    pub platform: Option<String>,
+    pub digests: Vec<String>,
+}
+
+/// Errors from parsing a `Gemfile.lock`, the first value is the (one indexed) line number
+#[derive(thiserror::Error, Debug, PartialEq, Eq, Clone)]
+pub enum GemfileLockError {
+    #[error("Line {0}: unresolved merge conflict marker `{1}`")]
+    MergeConflict(usize, String),
+
+    #[error("Line {0}: content is not inside of a section `{1}`")]
+    OutsideOfSection(usize, String),
+
+    #[error("Line {0}: unexpected indentation `{1}`")]
+    UnexpectedIndentation(usize, String),
+
+    #[error("Line {0}: cannot parse gem specification `{1}`")]
+    InvalidSpec(usize, String),
+
+    #[error("Line {0}: cannot parse dependency `{1}`")]
+    InvalidDependency(usize, String),
+
+    #[error("Line {0}: cannot parse checksum `{1}`")]
+    InvalidChecksum(usize, String),
+
+    #[error("Line {0}: cannot parse Ruby version `{1}`")]
+    InvalidRubyVersion(usize, String),
+
+    #[error(
+        "Line {0}: unknown Ruby engine `{1}`, must be one of `ruby`, `jruby`, or `truffleruby`"
+    )]
+    UnknownRubyEngine(usize, String),
+
+    #[error("Line {0}: cannot parse bundler version `{1}`")]
+    InvalidBundlerVersion(usize, String),
+}
+
+/// The top level section currently being parsed
+enum Section {
+    Source,
+    Platforms,
+    Dependencies,
+    Checksums,
+    RubyVersion,
+    BundledWith,
+    /// Sections that are not modeled (i.e. from a newer bundler version) are skipped
+    Unknown,
+}
+
+static SPEC_RE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"^(?<name>[^ (]+) \((?<version>[^-)]+)(?:-(?<platform>[^)]+))?\)$")
+        .expect("Internal error: Bad regex") // Checked via clippy
+});
+static DEPENDENCY_RE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"^(?<name>[^ (!]+)(?: \((?<requirements>[^)]*)\))?(?<pinned>!)?$")
+        .expect("Internal error: Bad regex") // Checked via clippy
+});
+static CHECKSUM_RE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(
+        r"^(?<name>[^ (]+) \((?<version>[^-)]+)(?:-(?<platform>[^)]+))?\)(?: (?<digests>\S+))?$",
+    )
+    .expect("Internal error: Bad regex") // Checked via clippy
+});
+static RUBY_VERSION_RE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(
+        r"^ruby (?<version>\d+\.\d+\.\d+(?:\.[a-z]+\d*)?)(?:p(?<patchlevel>-?\d+))?(?: \((?<engine>\w+) (?<engine_version>[^)]+)\))?$",
+    )
+    .expect("Internal error: Bad regex") // Checked via clippy
+});
+
 impl FromStr for GemfileLock {
-    type Err = std::convert::Infallible;
+    type Err = GemfileLockError;
 
     fn from_str(string: &str) -> Result<Self, Self::Err> {
-        let bundled_with_re = Regex::new("BUNDLED WITH\\s   (\\d+\\.\\d+\\.\\d+)")
-            .expect("Internal error: Bad regex"); // Checked via clippy
-        let main_ruby_version_re = Regex::new("RUBY VERSION\\s   ruby (\\d+\\.\\d+\\.\\d+)")
-            .expect("Internal error: Bad regex"); // Checked via clippy
-        let jruby_version_re =
-            Regex::new("\\(jruby ((\\d+|\\.)+)\\)").expect("Internal error: Bad regex"); // Checked via clippy
-
-        let bundler_version = match bundled_with_re.captures(string).and_then(|c| c.get(1)) {
-            Some(result) => BundlerVersion::Explicit(result.as_str().to_string()),
-            None => BundlerVersion::Default,
+        let mut lockfile = GemfileLock {
+            bundler_version: BundlerVersion::Default,
+            ruby_version: RubyVersion::Default,
+            sources: Vec::new(),
+            platforms: Vec::new(),
+            dependencies: Vec::new(),
+            checksums: Vec::new(),
+            version_files: Vec::new(),
         };
+        let mut section = None;
+
+        for (index, line) in string.lines().enumerate() {
+            let number = index + 1;
+            let line = line.trim_end();
+            let content = line.trim_start();
+            let indent = line.len() - content.len();
 
-        let ruby_version = match main_ruby_version_re.captures(string).and_then(|c| c.get(1)) {
-            Some(main_ruby_match) => match jruby_version_re.captures(string).and_then(|c| c.get(1))
+            if ["<<<<<<<", "|||||||", "=======", ">>>>>>>"]
+                .iter()
+                .any(|marker| line.starts_with(marker))
             {
-                Some(jruby_match) => RubyVersion::Explicit(format!(
-                    "{}-jruby-{}",
-                    main_ruby_match.as_str(),
-                    jruby_match.as_str()
-                )),
-                None => RubyVersion::Explicit(main_ruby_match.as_str().to_string()),
-            },
-            None => RubyVersion::Default,
-        };
+                return Err(GemfileLockError::MergeConflict(number, line.to_string()));
+            }
 
-        Ok(Self {
-            bundler_version,
-            ruby_version,
-        })
+            if content.is_empty() {
+                continue;
+            }
+
+            if indent == 0 {
+                section = Some(match content {
+                    "GEM" | "GIT" | "PATH" | "PLUGIN SOURCE" => {
+                        lockfile.sources.push(Source {
+                            kind: match content {
+                                "GEM" => SourceKind::Gem,
+                                "GIT" => SourceKind::Git,
+                                "PATH" => SourceKind::Path,
+                                _ => SourceKind::Plugin,
+                            },
+                            remotes: Vec::new(),
+                            options: Vec::new(),
+                            specs: Vec::new(),
+                        });
+                        Section::Source
+                    }
+                    "PLATFORMS" => Section::Platforms,
+                    "DEPENDENCIES" => Section::Dependencies,
+                    "CHECKSUMS" => Section::Checksums,
+                    "RUBY VERSION" => Section::RubyVersion,
+                    "BUNDLED WITH" => Section::BundledWith,
+                    _ => Section::Unknown,
+                });
+                continue;
+            }
+
+            match &section {
+                None => return Err(GemfileLockError::OutsideOfSection(number, line.to_string())),
+                Some(Section::Unknown) => {}
+                Some(Section::Source) => {
+                    let source = lockfile.sources.last_mut().ok_or_else(|| {
+                        GemfileLockError::UnexpectedIndentation(number, line.to_string())
+                    })?;
+                    push_source_line(source, number, line)?;
+                }
+                Some(Section::Platforms) => lockfile.platforms.push(content.to_string()),
+                Some(Section::Dependencies) => {
+                    if indent != 2 {
+                        return Err(GemfileLockError::UnexpectedIndentation(
+                            number,
+                            line.to_string(),
+                        ));
+                    }
+                    lockfile
+                        .dependencies
+                        .push(parse_dependency(content).ok_or_else(|| {
+                            GemfileLockError::InvalidDependency(number, content.to_string())
+                        })?);
+                }
+                Some(Section::Checksums) => {
+                    lockfile
+                        .checksums
+                        .push(parse_checksum(content).ok_or_else(|| {
+                            GemfileLockError::InvalidChecksum(number, content.to_string())
+                        })?);
+                }
+                Some(Section::RubyVersion) => {
+                    lockfile.ruby_version = parse_ruby_version(content)
+                        .ok_or_else(|| {
+                            GemfileLockError::InvalidRubyVersion(number, content.to_string())
+                        })?
+                        .map_err(|engine| GemfileLockError::UnknownRubyEngine(number, engine))?;
+                }
+                Some(Section::BundledWith) => {
+                    GemVersion::from_str(content).map_err(|_| {
+                        GemfileLockError::InvalidBundlerVersion(number, content.to_string())
+                    })?;
+                    lockfile.bundler_version = BundlerVersion::Explicit(content.to_string());
+                }
+            }
+        }
+
+        Ok(lockfile)
     }
 }
 
+/// Adds an indented line of a `GEM`, `GIT`, `PATH`, or `PLUGIN SOURCE` section to the source
+///
+/// - Two spaces: `remote:`, `specs:` or other `key: value` options
+/// - Four spaces: A resolved spec such as `puma (6.4.2)`
+/// - Six spaces: A dependency of the previous spec such as `nio4r (~> 2.0)`
+fn push_source_line(
+    source: &mut Source,
+    number: usize,
+    line: &str,
+) -> Result<(), GemfileLockError> {
+    let content = line.trim_start();
+    let unexpected_indent = || GemfileLockError::UnexpectedIndentation(number, line.to_string());
+
+    match line.len() - content.len() {
+        2 => match content.split_once(": ") {
+            Some(("remote", remote)) => source.remotes.push(remote.to_string()),
+            Some((key, value)) => source.options.push((key.to_string(), value.to_string())),
+            None if content == "specs:" => {}
+            None => return Err(unexpected_indent()),
+        },
+        4 => source.specs.push(
+            parse_spec(content)
+                .ok_or_else(|| GemfileLockError::InvalidSpec(number, content.to_string()))?,
+        ),
+        6 => {
+            let dependency = parse_dependency(content)
+                .ok_or_else(|| GemfileLockError::InvalidDependency(number, content.to_string()))?;
+            source
+                .specs
+                .last_mut()
+                .ok_or_else(unexpected_indent)?
+                .dependencies
+                .push(dependency);
+        }
+        _ => return Err(unexpected_indent()),
+    }
+    Ok(())
+}
+
+fn parse_spec(content: &str) -> Option<Spec> {
+    SPEC_RE.captures(content).map(|captures| Spec {
+        name: captures["name"].to_string(),
+        version: captures["version"].to_string(),
+        platform: captures
+            .name("platform")
+            .map(|platform| platform.as_str().to_string()),
+        dependencies: Vec::new(),
+    })
+}
+
+fn parse_dependency(content: &str) -> Option<Dependency> {
+    DEPENDENCY_RE.captures(content).map(|captures| Dependency {
+        name: captures["name"].to_string(),
+        requirements: captures
+            .name("requirements")
+            .map(|requirements| {
+                requirements
+                    .as_str()
+                    .split(", ")
+                    .map(str::to_string)
+                    .collect()
+            })
+            .unwrap_or_default(),
+        pinned: captures.name("pinned").is_some(),
+    })
+}
+
+fn parse_checksum(content: &str) -> Option<Checksum> {
+    CHECKSUM_RE.captures(content).map(|captures| Checksum {
+        name: captures["name"].to_string(),
+        version: captures["version"].to_string(),
+        platform: captures
+            .name("platform")
+            .map(|platform| platform.as_str().to_string()),
+        digests: captures
+            .name("digests")
+            .map(|digests| digests.as_str().split(',').map(str::to_string).collect())
+            .unwrap_or_default(),
+    })
+}
+
+/// Returns `None` when the line cannot be parsed and an error containing the engine name
+/// when the engine is not supported
+fn parse_ruby_version(content: &str) -> Option<Result<RubyVersion, String>> {
+    let captures = RUBY_VERSION_RE.captures(content)?;
+    let version = captures["version"].to_string();
+    let patchlevel = captures
+        .name("patchlevel")
+        .map(|patchlevel| patchlevel.as_str().to_string());
+
+    Some(
+        match captures.name("engine").zip(captures.name("engine_version")) {
+            Some((engine, engine_version)) => {
+                RubyEngine::from_str(engine.as_str()).map(|engine| RubyVersion::Explicit {
+                    version,
+                    patchlevel,
+                    engine,
+                    engine_version: engine_version.as_str().to_string(),
+                })
+            }
+            None => Ok(RubyVersion::Explicit {
+                engine: RubyEngine::Mri,
+                engine_version: version.clone(),
+                version,
+                patchlevel,
+            }),
+        },
+    )
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -182,7 +633,12 @@ BUNDLED WITH
         );
         assert_eq!(
             info.ruby_version,
-            RubyVersion::Explicit("3.1.0".to_string())
+            RubyVersion::Explicit {
+                version: String::from("3.1.0"),
+                patchlevel: Some(String::from("-1")),
+                engine: RubyEngine::Mri,
+                engine_version: String::from("3.1.0"),
+            }
         );
     }
 
@@ -211,7 +667,299 @@ DEPENDENCIES
 
         assert_eq!(
             info.ruby_version,
-            RubyVersion::Explicit(String::from("2.5.7-jruby-9.2.13.0"))
+            RubyVersion::Explicit {
+                version: String::from("2.5.7"),
+                patchlevel: Some(String::from("001")),
+                engine: RubyEngine::Jruby,
+                engine_version: String::from("9.2.13.0"),
+            }
+        );
+    }
+
+    #[test]
+    #[allow(clippy::too_many_lines)]
+    fn test_parse_sources_and_sections() {
+        let info = GemfileLock::from_str(
+            r"
+GIT
+  remote: https://github.com/rails/rails.git
+  revision: 5b7a6ff8ba8d9fdc5b4d5d6a5a4e3b2a1f0e9d8c
+  branch: main
+  specs:
+    rails (7.2.0.alpha)
+      actionpack (= 7.2.0.alpha)
+
+PATH
+  remote: vendor/my_gem
+  specs:
+    my_gem (0.1.0)
+
+GEM
+  remote: https://rubygems.org/
+  specs:
+    nokogiri (1.16.0-x86_64-linux)
+      racc (~> 1.4)
+    puma (6.4.2)
+      nio4r (~> 2.0)
+    racc (1.7.3)
+
+PLATFORMS
+  ruby
+  x86_64-linux
+
+DEPENDENCIES
+  my_gem!
+  nokogiri
+  puma (>= 5.0, < 7)
+  rails!
+
+CHECKSUMS
+  nokogiri (1.16.0-x86_64-linux) sha256=aa11,sha512=bb22
+  rails (7.2.0.alpha)
+
+RUBY VERSION
+   ruby 3.3.0p0
+
+BUNDLED WITH
+   2.5.3
+",
+        )
+        .unwrap();
+
+        assert_eq!(
+            info.sources.iter().map(|s| s.kind).collect::<Vec<_>>(),
+            vec![SourceKind::Git, SourceKind::Path, SourceKind::Gem]
+        );
+        let git = &info.sources[0];
+        assert_eq!(
+            git.remotes,
+            vec![String::from("https://github.com/rails/rails.git")]
+        );
+        assert_eq!(
+            git.options,
+            vec![
+                (
+                    String::from("revision"),
+                    String::from("5b7a6ff8ba8d9fdc5b4d5d6a5a4e3b2a1f0e9d8c")
+                ),
+                (String::from("branch"), String::from("main"))
+            ]
+        );
+        assert_eq!(
+            git.specs[0].dependencies,
+            vec![Dependency {
+                name: String::from("actionpack"),
+                requirements: vec![String::from("= 7.2.0.alpha")],
+                pinned: false,
+            }]
+        );
+
+        let nokogiri = info.specs().find(|spec| spec.name == "nokogiri").unwrap();
+        assert_eq!(nokogiri.version, "1.16.0");
+        assert_eq!(nokogiri.platform, Some(String::from("x86_64-linux")));
+        assert!(info.has_gem("racc"));
+        assert!(info.has_gem("my_gem"));
+        assert!(!info.has_gem("barnes"));
+
+        assert_eq!(
+            info.platforms,
+            vec![String::from("ruby"), String::from("x86_64-linux")]
+        );
+        assert_eq!(
+            info.dependencies[2],
+            Dependency {
+                name: String::from("puma"),
+                requirements: vec![String::from(">= 5.0"), String::from("< 7")],
+                pinned: false,
+            }
+        );
+        assert!(info.dependencies[3].pinned);
+
+        assert_eq!(
+            info.checksums,
+            vec![
+                Checksum {
+                    name: String::from("nokogiri"),
+                    version: String::from("1.16.0"),
+                    platform: Some(String::from("x86_64-linux")),
+                    digests: vec![String::from("sha256=aa11"), String::from("sha512=bb22")],
+                },
+                Checksum {
+                    name: String::from("rails"),
+                    version: String::from("7.2.0.alpha"),
+                    platform: None,
+                    digests: Vec::new(),
+                }
+            ]
+        );
+        assert_eq!(
+            info.ruby_version,
+            RubyVersion::Explicit {
+                version: String::from("3.3.0"),
+                patchlevel: Some(String::from("0")),
+                engine: RubyEngine::Mri,
+                engine_version: String::from("3.3.0"),
+            }
+        );
+        assert_eq!(
+            info.bundler_version,
+            BundlerVersion::Explicit(String::from("2.5.3"))
+        );
+    }
+
+    #[test]
+    fn test_merge_conflict_errors() {
+        let error = GemfileLock::from_str(
+            r"GEM
+  remote: https://rubygems.org/
+  specs:
+<<<<<<< HEAD
+    puma (6.4.2)
+=======
+    puma (6.4.1)
+>>>>>>> main
+",
+        )
+        .unwrap_err();
+
+        assert_eq!(
+            error,
+            GemfileLockError::MergeConflict(4, String::from("<<<<<<< HEAD"))
+        );
+    }
+
+    #[test]
+    fn test_malformed_lines_error() {
+        assert_eq!(
+            GemfileLock::from_str("GEM\n  specs:\n    puma\n").unwrap_err(),
+            GemfileLockError::InvalidSpec(3, String::from("puma"))
+        );
+        assert_eq!(
+            GemfileLock::from_str("  puma (6.4.2)\n").unwrap_err(),
+            GemfileLockError::OutsideOfSection(1, String::from("  puma (6.4.2)"))
+        );
+        assert_eq!(
+            GemfileLock::from_str("RUBY VERSION\n   jruby\n").unwrap_err(),
+            GemfileLockError::InvalidRubyVersion(2, String::from("jruby"))
+        );
+        assert_eq!(
+            GemfileLock::from_str("BUNDLED WITH\n   two\n").unwrap_err(),
+            GemfileLockError::InvalidBundlerVersion(2, String::from("two"))
+        );
+    }
+
+    #[test]
+    fn test_unknown_sections_are_skipped() {
+        let info = GemfileLock::from_str(
+            r"
+FUTURE SECTION
+  some: value
+
+BUNDLED WITH
+   2.5.3
+",
+        )
+        .unwrap();
+        assert_eq!(
+            info.bundler_version,
+            BundlerVersion::Explicit(String::from("2.5.3"))
+        );
+    }
+
+    #[test]
+    fn test_prerelease_ruby_versions() {
+        for (line, version, patchlevel) in [
+            ("ruby 3.4.0.preview2", "3.4.0.preview2", None),
+            ("ruby 3.4.0.preview2p-1", "3.4.0.preview2", Some("-1")),
+            ("ruby 3.3.0.rc1", "3.3.0.rc1", None),
+            ("ruby 2.7.4p191", "2.7.4", Some("191")),
+        ] {
+            let info = GemfileLock::from_str(&format!("RUBY VERSION\n   {line}\n")).unwrap();
+            assert_eq!(
+                info.ruby_version,
+                RubyVersion::Explicit {
+                    version: String::from(version),
+                    patchlevel: patchlevel.map(String::from),
+                    engine: RubyEngine::Mri,
+                    engine_version: String::from(version),
+                }
+            );
+            assert_eq!(info.resolve_ruby("3.1.3").to_string(), version);
+        }
+    }
+
+    #[test]
+    fn test_truffleruby() {
+        let info = GemfileLock::from_str(
+            r"
+RUBY VERSION
+   ruby 3.2.2p0 (truffleruby 24.0.0)
+",
+        )
+        .unwrap();
+
+        let ruby = info.resolve_ruby("3.1.3");
+        assert_eq!(
+            ruby,
+            ResolvedRuby {
+                engine: RubyEngine::Truffleruby,
+                engine_version: String::from("24.0.0"),
+                version: ResolvedRubyVersion(String::from("3.2.2")),
+            }
+        );
+        assert_eq!(ruby.to_string(), "3.2.2 (truffleruby 24.0.0)");
+    }
+
+    #[test]
+    fn test_unknown_engine() {
+        assert_eq!(
+            GemfileLock::from_str("RUBY VERSION\n   ruby 2.3.1p0 (rbx 3.69)\n").unwrap_err(),
+            GemfileLockError::UnknownRubyEngine(2, String::from("rbx"))
+        );
+    }
+
+    #[test]
+    fn test_version_files() {
+        let version_file = |file, requirement: &str| VersionFile {
+            file,
+            requirement: requirement.to_string(),
+        };
+        let info = GemfileLock::from_str("").unwrap();
+        assert_eq!(info.ruby_source(), "default");
+
+        let info = info.with_version_files(vec![
+            version_file(".tool-versions", "3.3.6"),
+            version_file(".ruby-version", "3.2.6"),
+        ]);
+        assert_eq!(info.ruby_source(), ".tool-versions");
+        assert_eq!(info.resolve_ruby("3.1.3"), ResolvedRuby::mri("3.3.6"));
+
+        let info = info.with_version_files(vec![version_file(".ruby-version", "3.3")]);
+        assert_eq!(info.ruby_source(), ".ruby-version");
+        assert_eq!(info.resolve_ruby("3.1.3"), ResolvedRuby::mri("3.1.3"));
+
+        let info = GemfileLock::from_str("RUBY VERSION\n   ruby 3.4.1p0\n")
+            .unwrap()
+            .with_version_files(vec![version_file(".ruby-version", "3.3.6")]);
+        assert_eq!(info.ruby_source(), "Gemfile.lock");
+        assert_eq!(info.resolve_ruby("3.1.3"), ResolvedRuby::mri("3.4.1"));
+    }
+
+    #[test]
+    fn test_resolved_ruby_from_legacy_version() {
+        assert_eq!(
+            ResolvedRuby::from_legacy_version(&ResolvedRubyVersion(String::from(
+                "2.5.7-jruby-9.2.13.0"
+            ))),
+            ResolvedRuby {
+                engine: RubyEngine::Jruby,
+                engine_version: String::from("9.2.13.0"),
+                version: ResolvedRubyVersion(String::from("2.5.7")),
+            }
+        );
+        assert_eq!(
+            ResolvedRuby::from_legacy_version(&ResolvedRubyVersion(String::from("3.1.3"))),
+            ResolvedRuby::mri("3.1.3")
         );
     }
 }
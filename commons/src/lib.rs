@@ -1,6 +1,7 @@
 pub mod cache;
 pub mod display;
 pub mod gem_version;
+pub mod gemfile;
 pub mod gemfile_lock;
 #[deprecated(
     since = "0.0.0",
@@ -9,3 +10,5 @@ pub mod gemfile_lock;
 pub mod layer;
 pub mod metadata_digest;
 pub mod output;
+pub mod ruby_requirement;
+pub mod ruby_version_file;
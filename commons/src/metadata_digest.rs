@@ -160,7 +160,23 @@ impl MetadataDigest {
     ///
     /// Errors if one of the files cannot be read from disk.
     pub fn new_env_files(platform: &impl Platform, files: &[&Path]) -> Result<Self, DigestError> {
-        let env = PlatformEnvDigest::new(platform);
+        Self::new_env_files_ignoring(platform, files, &[])
+    }
+
+    /// Same as `new_env_files` but leaves the `ignored` environment variables out of the hash
+    ///
+    /// Use it for variables that tune a command without changing its result, such as the
+    /// number of parallel jobs, so that changing them does not register as a change.
+    ///
+    /// # Errors
+    ///
+    /// Errors if one of the files cannot be read from disk.
+    pub fn new_env_files_ignoring(
+        platform: &impl Platform,
+        files: &[&Path],
+        ignored: &[&str],
+    ) -> Result<Self, DigestError> {
+        let env = PlatformEnvDigest::new(platform, ignored);
         let files = PathsDigest::new(files)?;
 
         Ok(MetadataDigest {
@@ -240,10 +256,17 @@ struct ShaString(String);
 #[derive(Serialize, Deserialize, Clone, Debug, Hash, PartialEq, Eq)]
 struct PlatformEnvDigest(ShaString);
 impl PlatformEnvDigest {
-    fn new(platform: &impl Platform) -> Self {
-        let env = platform.env();
-
-        PlatformEnvDigest(sha_from_env(env))
+    fn new(platform: &impl Platform, ignored: &[&str]) -> Self {
+        let env = platform
+            .env()
+            .iter()
+            .filter(|(key, _)| !ignored.iter().any(|ignored| key.as_os_str() == *ignored))
+            .fold(Env::new(), |mut env, (key, value)| {
+                env.insert(key, value);
+                env
+            });
+
+        PlatformEnvDigest(sha_from_env(&env))
     }
 }
 
@@ -504,6 +527,30 @@ mod test {
         );
     }
 
+    #[test]
+    fn metadata_platform_env_ignoring() {
+        let mut env = Env::new();
+        env.insert("RAILS_MASTER_KEY", "abc");
+        let one = MetadataDigest::new_env_files_ignoring(
+            &FakePlatform { env: env.clone() },
+            &[],
+            &["BUNDLE_JOBS"],
+        )
+        .unwrap();
+
+        env.insert("BUNDLE_JOBS", "8");
+        let two = MetadataDigest::new_env_files_ignoring(
+            &FakePlatform { env: env.clone() },
+            &[],
+            &["BUNDLE_JOBS"],
+        )
+        .unwrap();
+        assert_eq!(None, two.changed(&one));
+
+        let three = MetadataDigest::new_env_files(&FakePlatform { env }, &[]).unwrap();
+        assert!(three.changed(&one).unwrap().platform_env);
+    }
+
     #[test]
     fn metadata_digest_different_file_names() {
         let tempdir = tempfile::tempdir().unwrap();
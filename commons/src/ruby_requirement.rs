@@ -0,0 +1,300 @@
+use crate::gem_version::GemVersion;
+use regex::Regex;
+use std::fmt::Display;
+use std::str::FromStr;
+use std::sync::LazyLock;
+
+static CONSTRAINT_RE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"^(?:(?<operator>~>|>=|<=|!=|=|>|<)\s*)?(?<version>\d+(?:\.[0-9a-zA-Z]+)*)$")
+        .expect("Internal error: Bad regex") // Checked via clippy
+});
+
+/// # A requirement for a Ruby version such as `~> 3.3` or `3.2`
+///
+/// Operators follow `Gem::Requirement` semantics, multiple constraints are separated by commas
+/// i.e. `>= 3.1, < 3.4`. A version without an operator that has fewer than three segments is
+/// treated as a prefix so `3.2` matches the newest `3.2.x` (like `~> 3.2.0`) rather than only
+/// `3.2.0`, which is how version managers read `.ruby-version`.
+///
+/// Prerelease versions such as `3.4.0.preview2` only satisfy the requirement when one of its
+/// constraints is itself a prerelease.
+///
+/// Example:
+///
+/// ```rust
+/// use std::str::FromStr;
+/// use commons::gem_version::GemVersion;
+/// use commons::ruby_requirement::RubyRequirement;
+///
+/// let requirement = RubyRequirement::from_str("~> 3.3").unwrap();
+/// assert!(requirement.satisfied_by(&GemVersion::from_str("3.4.1").unwrap()));
+/// assert!(!requirement.satisfied_by(&GemVersion::from_str("4.0.0").unwrap()));
+/// ```
+#[derive(Debug, Clone)]
+pub struct RubyRequirement {
+    requirement: String,
+    constraints: Vec<Constraint>,
+}
+
+#[derive(Debug, Clone)]
+enum Constraint {
+    Equal(GemVersion),
+    NotEqual(GemVersion),
+    Greater(GemVersion),
+    GreaterOrEqual(GemVersion),
+    Less(GemVersion),
+    LessOrEqual(GemVersion),
+    /// Greater or equal to the first version and less than the second
+    Between(GemVersion, GemVersion),
+}
+
+#[derive(thiserror::Error, Debug, PartialEq, Eq, Clone)]
+pub enum RubyRequirementError {
+    #[error("Invalid Ruby version requirement `{0}`, expected a version such as `3.3.6`, `3.3`, or `~> 3.3`")]
+    InvalidRequirement(String),
+}
+
+impl RubyRequirement {
+    /// Returns the version when only a single version satisfies the requirement i.e. `3.3.6` or `= 3.3.6`
+    #[must_use]
+    pub fn exact(&self) -> Option<&GemVersion> {
+        match self.constraints.as_slice() {
+            [Constraint::Equal(version)] => Some(version),
+            _ => None,
+        }
+    }
+
+    #[must_use]
+    pub fn satisfied_by(&self, version: &GemVersion) -> bool {
+        if version.is_prerelease() && !self.allows_prerelease() {
+            return false;
+        }
+
+        self.constraints.iter().all(|constraint| match constraint {
+            Constraint::Equal(v) => version == v,
+            Constraint::NotEqual(v) => version != v,
+            Constraint::Greater(v) => version > v,
+            Constraint::GreaterOrEqual(v) => version >= v,
+            Constraint::Less(v) => version < v,
+            Constraint::LessOrEqual(v) => version <= v,
+            Constraint::Between(low, high) => version >= low && version < high,
+        })
+    }
+
+    /// Returns the newest item whose version satisfies the requirement
+    pub fn newest<T>(
+        &self,
+        items: impl IntoIterator<Item = T>,
+        version: impl Fn(&T) -> Option<GemVersion>,
+    ) -> Option<T> {
+        items
+            .into_iter()
+            .filter_map(|item| version(&item).map(|v| (v, item)))
+            .filter(|(v, _)| self.satisfied_by(v))
+            .reduce(|newest, current| {
+                if current.0 > newest.0 {
+                    current
+                } else {
+                    newest
+                }
+            })
+            .map(|(_, item)| item)
+    }
+
+    fn allows_prerelease(&self) -> bool {
+        self.constraints.iter().any(|constraint| match constraint {
+            Constraint::Equal(v)
+            | Constraint::NotEqual(v)
+            | Constraint::Greater(v)
+            | Constraint::GreaterOrEqual(v)
+            | Constraint::Less(v)
+            | Constraint::LessOrEqual(v)
+            | Constraint::Between(v, _) => v.is_prerelease(),
+        })
+    }
+}
+
+impl Display for RubyRequirement {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.requirement)
+    }
+}
+
+impl FromStr for RubyRequirement {
+    type Err = RubyRequirementError;
+
+    fn from_str(requirement: &str) -> Result<Self, Self::Err> {
+        let requirement = requirement.trim();
+        let invalid = || RubyRequirementError::InvalidRequirement(requirement.to_string());
+
+        let constraints = requirement
+            .split(',')
+            .map(|part| parse_constraint(part.trim()).ok_or_else(invalid))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(Self {
+            requirement: requirement.to_string(),
+            constraints,
+        })
+    }
+}
+
+fn parse_constraint(constraint: &str) -> Option<Constraint> {
+    let captures = CONSTRAINT_RE.captures(constraint)?;
+    let version_str = &captures["version"];
+    let version = GemVersion::from_str(version_str).ok()?;
+
+    Some(match captures.name("operator").map(|m| m.as_str()) {
+        Some("~>") => Constraint::Between(version, bump(version_str, true)?),
+        None if release_segments(version_str).len() < 3 && !version.is_prerelease() => {
+            Constraint::Between(version, bump(version_str, false)?)
+        }
+        None | Some("=") => Constraint::Equal(version),
+        Some("!=") => Constraint::NotEqual(version),
+        Some(">") => Constraint::Greater(version),
+        Some(">=") => Constraint::GreaterOrEqual(version),
+        Some("<") => Constraint::Less(version),
+        Some("<=") => Constraint::LessOrEqual(version),
+        Some(_) => return None,
+    })
+}
+
+/// The leading numeric segments of a version string i.e. `[3, 4, 0]` for `3.4.0.preview2`
+///
+/// Computed from the string as `GemVersion` drops trailing zero segments.
+fn release_segments(version: &str) -> Vec<u32> {
+    version
+        .split('.')
+        .map_while(|segment| segment.parse::<u32>().ok())
+        .collect()
+}
+
+/// The exclusive upper bound of a pessimistic (`pop_last`) or prefix requirement
+///
+/// `~> 3.3.1` is bumped to `3.4` and `~> 3.3` to `4`, like `Gem::Version#bump`. The prefix `3.3`
+/// is bumped to `3.4`.
+fn bump(version: &str, pop_last: bool) -> Option<GemVersion> {
+    let mut segments = release_segments(version);
+    if pop_last && segments.len() > 1 {
+        segments.pop();
+    }
+    let last = segments.last_mut()?;
+    *last = last.checked_add(1)?;
+
+    GemVersion::from_str(
+        &segments
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>()
+            .join("."),
+    )
+    .ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn v(version: &str) -> GemVersion {
+        GemVersion::from_str(version).unwrap()
+    }
+
+    fn r(requirement: &str) -> RubyRequirement {
+        RubyRequirement::from_str(requirement).unwrap()
+    }
+
+    #[test]
+    fn test_pessimistic() {
+        assert!(r("~> 3.3").satisfied_by(&v("3.3.0")));
+        assert!(r("~> 3.3").satisfied_by(&v("3.9.9")));
+        assert!(!r("~> 3.3").satisfied_by(&v("3.2.9")));
+        assert!(!r("~> 3.3").satisfied_by(&v("4.0.0")));
+
+        assert!(r("~> 3.3.1").satisfied_by(&v("3.3.6")));
+        assert!(!r("~> 3.3.1").satisfied_by(&v("3.3.0")));
+        assert!(!r("~> 3.3.1").satisfied_by(&v("3.4.0")));
+    }
+
+    #[test]
+    fn test_prefix() {
+        assert!(r("3.2").satisfied_by(&v("3.2.0")));
+        assert!(r("3.2").satisfied_by(&v("3.2.6")));
+        assert!(!r("3.2").satisfied_by(&v("3.3.0")));
+        assert!(!r("3.2").satisfied_by(&v("3.1.9")));
+
+        assert!(r("3").satisfied_by(&v("3.4.1")));
+        assert!(!r("3").satisfied_by(&v("4.0.0")));
+
+        // Trailing zeros are significant in a prefix
+        assert!(!r("3.0").satisfied_by(&v("3.1.0")));
+        assert!(r("3.0").satisfied_by(&v("3.0.7")));
+    }
+
+    #[test]
+    fn test_exact() {
+        assert!(r("3.2.2").satisfied_by(&v("3.2.2")));
+        assert!(!r("3.2.2").satisfied_by(&v("3.2.3")));
+        assert!(r("= 3.2.2").exact().is_some());
+        assert!(r("3.2.2").exact().is_some());
+        assert!(r("3.2").exact().is_none());
+        assert!(r("~> 3.2.2").exact().is_none());
+    }
+
+    #[test]
+    fn test_comparison_operators() {
+        let requirement = r(">= 3.1, < 3.4");
+        assert!(requirement.satisfied_by(&v("3.1.0")));
+        assert!(requirement.satisfied_by(&v("3.3.6")));
+        assert!(!requirement.satisfied_by(&v("3.4.0")));
+        assert!(!requirement.satisfied_by(&v("3.0.7")));
+
+        assert!(r("> 3.3.0").satisfied_by(&v("3.3.1")));
+        assert!(!r("> 3.3.0").satisfied_by(&v("3.3.0")));
+        assert!(r("<= 3.3.0").satisfied_by(&v("3.3.0")));
+        assert!(!r("!= 3.3.0").satisfied_by(&v("3.3.0")));
+    }
+
+    #[test]
+    fn test_prerelease() {
+        assert!(!r("~> 3.4").satisfied_by(&v("3.5.0.preview1")));
+        assert!(!r("3").satisfied_by(&v("3.5.0.preview1")));
+        assert!(r("3.5.0.preview1").satisfied_by(&v("3.5.0.preview1")));
+        assert!(r(">= 3.5.0.preview1").satisfied_by(&v("3.5.0.preview2")));
+    }
+
+    #[test]
+    fn test_newest() {
+        let versions = [
+            "3.2.6",
+            "3.3.0",
+            "3.3.6",
+            "3.3.10",
+            "3.4.1",
+            "3.5.0.preview1",
+        ];
+        let newest = |requirement: &str| r(requirement).newest(versions, |s| s.parse().ok());
+
+        assert_eq!(Some("3.3.10"), newest("3.3"));
+        assert_eq!(Some("3.4.1"), newest("~> 3.3"));
+        assert_eq!(Some("3.2.6"), newest("< 3.3"));
+        assert_eq!(None, newest("~> 2.7"));
+    }
+
+    #[test]
+    fn test_invalid() {
+        for requirement in ["", "junk", "~>", ">> 3.3", "3..3", "ruby-3.3", "3.3,"] {
+            assert_eq!(
+                Err(RubyRequirementError::InvalidRequirement(
+                    requirement.to_string()
+                )),
+                RubyRequirement::from_str(requirement).map(|r| r.to_string()),
+                "Expected {requirement:?} to be invalid"
+            );
+        }
+    }
+
+    #[test]
+    fn test_display() {
+        assert_eq!("~> 3.3", r(" ~> 3.3 ").to_string());
+    }
+}
@@ -0,0 +1,152 @@
+use std::io;
+use std::path::Path;
+
+/// # Ruby versions from version manager files
+///
+/// Version managers read the Ruby version of a project from a `.ruby-version` file (rbenv,
+/// chruby, rvm) or a `.tool-versions` file (asdf, mise). Applications that set the version in
+/// one of these files and not in the `Gemfile` would otherwise be built with the default Ruby.
+///
+/// Files are read in priority order, `.ruby-version` first. The value is the raw requirement as
+/// written, such as `3.3.6` or `3.3`, with the optional `ruby-` prefix removed.
+///
+/// ```rust
+/// use commons::ruby_version_file::VersionFile;
+///
+/// assert_eq!(Some("3.3.6"), VersionFile::parse_ruby_version("ruby-3.3.6\n"));
+/// assert_eq!(
+///     Some("3.2.6"),
+///     VersionFile::parse_tool_versions("nodejs 20.11.0\nruby 3.2.6 3.1.6\n")
+/// );
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VersionFile {
+    /// File name relative to the application directory such as `.ruby-version`
+    pub file: &'static str,
+    pub requirement: String,
+}
+
+pub const RUBY_VERSION_FILE: &str = ".ruby-version";
+pub const TOOL_VERSIONS_FILE: &str = ".tool-versions";
+
+impl VersionFile {
+    /// Reads every version file in the application directory that declares a Ruby version, in
+    /// priority order
+    ///
+    /// # Errors
+    ///
+    /// Errors if a file exists but cannot be read.
+    pub fn read_all(app_dir: &Path) -> Result<Vec<Self>, io::Error> {
+        let mut files = Vec::new();
+        for (file, parse) in [
+            (
+                RUBY_VERSION_FILE,
+                Self::parse_ruby_version as fn(&str) -> Option<&str>,
+            ),
+            (TOOL_VERSIONS_FILE, Self::parse_tool_versions),
+        ] {
+            let contents = match fs_err::read_to_string(app_dir.join(file)) {
+                Ok(contents) => contents,
+                Err(error) if error.kind() == io::ErrorKind::NotFound => continue,
+                Err(error) => return Err(error),
+            };
+            if let Some(requirement) = parse(&contents) {
+                files.push(Self {
+                    file,
+                    requirement: requirement.to_string(),
+                });
+            }
+        }
+        Ok(files)
+    }
+
+    /// Returns the version from the contents of a `.ruby-version` file
+    ///
+    /// Version managers accept an optional `ruby-` prefix i.e. `ruby-3.3.6`.
+    #[must_use]
+    pub fn parse_ruby_version(contents: &str) -> Option<&str> {
+        contents
+            .lines()
+            .map(str::trim)
+            .find(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(|line| line.strip_prefix("ruby-").unwrap_or(line))
+    }
+
+    /// Returns the version from the `ruby` line of a `.tool-versions` file
+    ///
+    /// A line may list several versions i.e. `ruby 3.3.6 3.2.6`, the first one is preferred.
+    /// Comments start with `#`.
+    #[must_use]
+    pub fn parse_tool_versions(contents: &str) -> Option<&str> {
+        contents.lines().find_map(|line| {
+            let line = line.split('#').next().unwrap_or_default();
+            let mut words = line.split_whitespace();
+            match (words.next(), words.next()) {
+                (Some("ruby"), Some(version)) => {
+                    Some(version.strip_prefix("ruby-").unwrap_or(version))
+                }
+                _ => None,
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_ruby_version() {
+        assert_eq!(Some("3.3.6"), VersionFile::parse_ruby_version("3.3.6\n"));
+        assert_eq!(Some("3.3"), VersionFile::parse_ruby_version("ruby-3.3"));
+        assert_eq!(
+            Some("3.3.6"),
+            VersionFile::parse_ruby_version("# comment\n\n  3.3.6  \n")
+        );
+        assert_eq!(None, VersionFile::parse_ruby_version("\n"));
+    }
+
+    #[test]
+    fn test_parse_tool_versions() {
+        assert_eq!(
+            Some("3.3.6"),
+            VersionFile::parse_tool_versions("ruby 3.3.6\n")
+        );
+        assert_eq!(
+            Some("3.2.6"),
+            VersionFile::parse_tool_versions(
+                "# ruby 3.1.0\nnodejs 20.11.0\nruby   3.2.6 system # local\n"
+            )
+        );
+        assert_eq!(
+            None,
+            VersionFile::parse_tool_versions("rubygems 3.5.0\nnodejs 20\n")
+        );
+        assert_eq!(None, VersionFile::parse_tool_versions("ruby\n"));
+    }
+
+    #[test]
+    fn test_read_all() {
+        let app_dir = tempfile::tempdir().unwrap();
+        assert_eq!(
+            Vec::<VersionFile>::new(),
+            VersionFile::read_all(app_dir.path()).unwrap()
+        );
+
+        fs_err::write(app_dir.path().join(TOOL_VERSIONS_FILE), "ruby 3.2.6\n").unwrap();
+        fs_err::write(app_dir.path().join(RUBY_VERSION_FILE), "3.3\n").unwrap();
+        assert_eq!(
+            vec![
+                VersionFile {
+                    file: RUBY_VERSION_FILE,
+                    requirement: String::from("3.3")
+                },
+                VersionFile {
+                    file: TOOL_VERSIONS_FILE,
+                    requirement: String::from("3.2.6")
+                }
+            ],
+            VersionFile::read_all(app_dir.path()).unwrap()
+        );
+    }
+}